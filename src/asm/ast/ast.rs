@@ -1,7 +1,7 @@
-use std::cell::Cell;
+use std::fmt;
 use std::str::FromStr;
 use std::vec::Vec;
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 
 extern crate bigint;
 use self::bigint::{U256, Uint};
@@ -9,38 +9,92 @@ use self::bigint::{U256, Uint};
 extern crate rustc_serialize;
 use self::rustc_serialize::hex::FromHex;
 
+use evm::opcode::Opcode;
+
 pub type Nid = u64;
 
+// Source Span
+//
+/// Byte range of a node within the parsed source
+#[derive(Debug, Clone, Copy, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+impl Span {
+  pub fn new(start: usize, end: usize) -> Span {
+    Span {
+      start: start,
+      end: end,
+    }
+  }
+
+  pub fn position(&self, source: &str) -> Position {
+    Position::new(source, self.start)
+  }
+
+  pub fn end_position(&self, source: &str) -> Position {
+    Position::new(source, self.end)
+  }
+}
+
+/// Line and column of a byte offset, both starting at 1
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Position {
+  pub line: usize,
+  pub column: usize,
+}
+
+impl Position {
+  pub fn new(source: &str, offset: usize) -> Position {
+    let before = &source[..offset];
+    Position {
+      line: before.chars().filter(|&c| c == '\n').count() + 1,
+      column: before.chars().rev().take_while(|&c| c != '\n').count() + 1,
+    }
+  }
+}
+
+impl fmt::Display for Position {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
 // Generic Node
 //
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Node<T> {
   pub id: Nid,
+  pub span: Span,
   node: T,
 }
 
 impl<T> Node<T> {
+  /// Nodes are given their ids once the tree they are in is complete, see
+  /// `ids::Numbered`
   pub fn new(t: T) -> Node<T> {
     Node {
       node: t,
-      id: Node::<T>::next_id(),
+      id: 0,
+      span: Span::default(),
     }
   }
 
-  pub fn unwrap(self) -> T {
-    self.node
+  pub fn with_span(mut self, span: Span) -> Node<T> {
+    self.span = span;
+    self
   }
 
-  fn next_id() -> Nid {
-    thread_local!{
-      static CURRENT_ID: Cell<Nid> = Cell::new(0)
-    };
+  /// Wraps this node in a new parent node covering the same span
+  pub fn wrap<U, F: FnOnce(Node<T>) -> U>(self, f: F) -> Node<U> {
+    let span = self.span;
+    Node::new(f(self)).with_span(span)
+  }
 
-    CURRENT_ID.with(|c| {
-                      let id = c.get();
-                      c.set(id + 1);
-                      id
-                    })
+  pub fn unwrap(self) -> T {
+    self.node
   }
 }
 
@@ -52,6 +106,55 @@ impl<T> Deref for Node<T> {
   }
 }
 
+impl<T> DerefMut for Node<T> {
+  fn deref_mut(&mut self) -> &mut T {
+    &mut self.node
+  }
+}
+
+// Object
+//
+/// Yul's container format, bundling code with sub-objects and data, e.g.
+/// constructor code with the runtime code it deploys
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Object {
+  pub name: String,
+  pub code: Node<Block>,
+  pub items: Vec<Node<ObjectItem>>,
+}
+
+impl Object {
+  pub fn new(name: &str, code: Node<Block>, items: Vec<Node<ObjectItem>>) -> Node<Object> {
+    Node::new(Object {
+                name: name.to_string(),
+                code: code,
+                items: items,
+              })
+  }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ObjectItem {
+  Object(Node<Object>),
+  Data(Node<Data>),
+}
+
+/// A named data segment, given as a string or hex literal
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Data {
+  pub name: String,
+  pub value: Node<Literal>,
+}
+
+impl Data {
+  pub fn new(name: &str, value: Node<Literal>) -> Node<Data> {
+    Node::new(Data {
+                name: name.to_string(),
+                value: value,
+              })
+  }
+}
+
 // Block
 //
 
@@ -75,10 +178,15 @@ pub enum Statement {
   VariableDeclaration(Node<VariableDeclaration>),
   Assignment(Node<Assignment>),
   Expression(Node<Expression>),
+  If(Node<If>),
   Switch(Node<Switch>),
   ForLoop(Node<ForLoop>),
   ControlOp(Node<ControlOp>),
   SubAssembly(Node<SubAssembly>),
+  LabelDefinition(Node<LabelDefinition>),
+  Instruction(Node<Instruction>),
+  StackAssignment(Node<StackAssignment>),
+  Error(Node<SyntaxError>),
 }
 
 // Expression
@@ -185,6 +293,23 @@ impl Case {
   }
 }
 
+// If
+//
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct If {
+  pub condition: Node<Expression>,
+  pub body: Node<Block>,
+}
+
+impl If {
+  pub fn new(c: Node<Expression>, b: Node<Block>) -> Node<If> {
+    Node::new(If {
+                condition: c,
+                body: b,
+              })
+  }
+}
+
 // For Loop
 //
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
@@ -212,6 +337,8 @@ impl ForLoop {
 pub enum ControlOp {
   Break,
   Continue,
+  /// Returns from the enclosing function
+  Leave,
 }
 
 // Sub-Assembly
@@ -231,6 +358,103 @@ impl SubAssembly {
   }
 }
 
+// Label Definition
+//
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct LabelDefinition {
+  pub identifier: Node<Identifier>,
+  /// What the label is jumped to with, where code before it can't fall
+  /// through to it with the same stack
+  pub stack: Option<LabelStack>,
+}
+
+impl LabelDefinition {
+  pub fn new(i: Node<Identifier>) -> Node<LabelDefinition> {
+    Node::new(LabelDefinition {
+      identifier: i,
+      stack: None,
+    })
+  }
+
+  pub fn with_stack(i: Node<Identifier>, stack: LabelStack) -> Node<LabelDefinition> {
+    Node::new(LabelDefinition {
+      identifier: i,
+      stack: Some(stack),
+    })
+  }
+
+  /// Variables the label declares for the values on the stack, if any
+  pub fn items(&self) -> Option<&Vec<Node<Identifier>>> {
+    match self.stack {
+      Some(LabelStack::Items(ref items)) => Some(items),
+      _ => None,
+    }
+  }
+}
+
+/// The values on the stack at a label, on top of the variables in scope
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LabelStack {
+  /// A number of values, `l[2]:`
+  Height(usize),
+  /// Variables for the values, the last one on top, `l[a, b]:`. They are
+  /// declared in the block following the label if there is one, and in the
+  /// rest of the enclosing block otherwise
+  Items(Vec<Node<Identifier>>),
+}
+
+impl LabelStack {
+  pub fn len(&self) -> usize {
+    match *self {
+      LabelStack::Height(height) => height,
+      LabelStack::Items(ref items) => items.len(),
+    }
+  }
+}
+
+// Instruction
+//
+/// Bare opcode in the instructional style, operating directly on the stack
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Instruction {
+  pub opcode: Opcode,
+}
+
+impl Instruction {
+  pub fn new(opcode: Opcode) -> Node<Instruction> {
+    Node::new(Instruction { opcode: opcode })
+  }
+}
+
+// Stack Assignment
+//
+/// Pops the top of the stack into a variable, i.e. `=: x`
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct StackAssignment {
+  pub identifier: Node<Identifier>,
+}
+
+impl StackAssignment {
+  pub fn new(i: Node<Identifier>) -> Node<StackAssignment> {
+    Node::new(StackAssignment { identifier: i })
+  }
+}
+
+// Syntax Error
+//
+/// Source skipped over by a recovering parse, see `asm::recovery`. A block
+/// left open at the end of the input ends in one with no text
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct SyntaxError {
+  pub text: String,
+}
+
+impl SyntaxError {
+  pub fn new(text: &str) -> Node<SyntaxError> {
+    Node::new(SyntaxError { text: text.to_string() })
+  }
+}
+
 // Function Call
 //
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
@@ -253,34 +477,107 @@ impl FunctionCall {
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Identifier {
   pub symbol: String,
+  /// Type annotation of a declared variable, e.g. `x:u256`
+  pub type_name: Option<String>,
 }
 
 impl Identifier {
   pub fn new(s: &str) -> Node<Identifier> {
-    Node::new(Identifier { symbol: s.to_string() })
+    Identifier::typed(s, None)
+  }
+
+  pub fn typed(s: &str, type_name: Option<String>) -> Node<Identifier> {
+    Node::new(Identifier {
+                symbol: s.to_string(),
+                type_name: type_name,
+              })
   }
 }
 
 // Literal
 //
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
-pub enum Literal {
+pub struct Literal {
+  pub value: LiteralValue,
+  /// Type annotation, e.g. `1:u256`
+  pub type_name: Option<String>,
+}
+
+impl Literal {
+  pub fn new(value: LiteralValue, type_name: Option<String>) -> Node<Literal> {
+    Node::new(Literal {
+                value: value,
+                type_name: type_name,
+              })
+  }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LiteralValue {
   HexNumber(Node<HexNumber>),
   DecNumber(Node<DecNumber>),
   StringLiteral(Node<StringLiteral>),
   HexLiteral(Node<HexLiteral>),
+  /// A literal that parses but can't be represented, left in the tree to be
+  /// reported at its location once parsing is done
+  Invalid(Node<LiteralError>),
+}
+
+impl LiteralValue {
+  /// The literal built from `result` at `span`, or the reason it couldn't be
+  pub fn checked<T, F>(result: Result<Node<T>, LiteralError>, wrap: F, span: Span) -> LiteralValue
+    where F: FnOnce(Node<T>) -> LiteralValue
+  {
+    match result {
+      Ok(node) => wrap(node.with_span(span)),
+      Err(error) => LiteralValue::Invalid(Node::new(error).with_span(span)),
+    }
+  }
+}
+
+// Literal Errors
+//
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LiteralError {
+  NumberOverflow,
+  StringOverflow,
+  InvalidHex,
+}
+
+impl LiteralError {
+  pub fn description(&self) -> &'static str {
+    match *self {
+      LiteralError::NumberOverflow => "number literal exceeds 256 bits",
+      LiteralError::StringOverflow => "string literal exceeds 32 bytes",
+      LiteralError::InvalidHex => "hex literal has invalid digits",
+    }
+  }
+}
+
+impl fmt::Display for LiteralError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.description())
+  }
 }
 
+/// Literals are pushed as a single stack word
+const WORD_BYTES: usize = 32;
+
 // String Literal
 //
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct StringLiteral {
-  pub string: String,
+  /// Contents with escape sequences decoded
+  pub bytes: Vec<u8>,
 }
 
 impl StringLiteral {
-  pub fn new(s: String) -> Node<StringLiteral> {
-    Node::new(StringLiteral { string: s })
+  pub fn new(bytes: Vec<u8>) -> Result<Node<StringLiteral>, LiteralError> {
+    if bytes.len() > WORD_BYTES {
+      return Err(LiteralError::StringOverflow);
+    }
+
+    Ok(Node::new(StringLiteral { bytes: bytes }))
   }
 }
 
@@ -293,8 +590,12 @@ pub struct HexLiteral {
 }
 
 impl HexLiteral {
-  pub fn new(bytes: &str) -> Node<HexLiteral> {
-    Node::new(HexLiteral { bytes: bytes.from_hex().unwrap() })
+  pub fn new(bytes: &str) -> Result<Node<HexLiteral>, LiteralError> {
+    match bytes.from_hex() {
+      Ok(ref bytes) if bytes.len() > WORD_BYTES => Err(LiteralError::StringOverflow),
+      Ok(bytes) => Ok(Node::new(HexLiteral { bytes: bytes })),
+      Err(_) => Err(LiteralError::InvalidHex),
+    }
   }
 }
 
@@ -304,21 +605,69 @@ impl HexLiteral {
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct HexNumber {
   pub uint: U256,
+  /// Digits as written, without the `0x` prefix
+  pub digits: String,
 }
 
 impl HexNumber {
-  pub fn new(uint: &str) -> Node<HexNumber> {
-    Node::new(HexNumber { uint: U256::from_str(uint).unwrap() })
+  pub fn new(uint: &str) -> Result<Node<HexNumber>, LiteralError> {
+    // leading zeros don't count towards the size
+    let significant = uint.find(|c| c != '0').map_or("", |i| &uint[i..]);
+    if significant.len() > WORD_BYTES * 2 {
+      return Err(LiteralError::NumberOverflow);
+    }
+
+    let value = match significant {
+      "" => Ok(U256::zero()),
+      significant => U256::from_str(significant),
+    };
+
+    match value {
+      Ok(value) => {
+        Ok(Node::new(HexNumber {
+          uint: value,
+          digits: uint.to_string(),
+        }))
+      }
+      Err(_) => Err(LiteralError::InvalidHex),
+    }
   }
 }
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct DecNumber {
   pub uint: U256,
+  /// Digits as written, including any leading zeros
+  pub digits: String,
 }
 
 impl DecNumber {
-  pub fn new(uint: &str) -> Node<DecNumber> {
-    Node::new(DecNumber { uint: U256::from_dec_str(uint).unwrap() })
+  pub fn new(uint: &str) -> Result<Node<DecNumber>, LiteralError> {
+    match U256::from_dec_str(uint) {
+      Ok(value) => {
+        Ok(Node::new(DecNumber {
+          uint: value,
+          digits: uint.to_string(),
+        }))
+      }
+      Err(_) => Err(LiteralError::NumberOverflow),
+    }
   }
 }
+
+#[test]
+fn it_constructs_literals_up_to_a_word() {
+  let max = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+  assert_eq!(DecNumber::new(max).unwrap().uint, U256::max_value());
+  assert_eq!(DecNumber::new("115792089237316195423570985008687907853269984665640564039457584007913129639936"),
+             Err(LiteralError::NumberOverflow));
+
+  let zeros = "0000000000000000000000000000000000000000000000000000000000000000ff";
+  assert_eq!(HexNumber::new(zeros).unwrap().uint, U256::from(255));
+  assert_eq!(HexNumber::new("1ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"),
+             Err(LiteralError::NumberOverflow));
+
+  assert!(StringLiteral::new(vec![b'a'; 32]).is_ok());
+  assert_eq!(StringLiteral::new(vec![b'a'; 33]), Err(LiteralError::StringOverflow));
+  assert_eq!(HexLiteral::new("zz"), Err(LiteralError::InvalidHex));
+}
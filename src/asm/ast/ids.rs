@@ -0,0 +1,160 @@
+//! Node ids, given out per tree so that they only depend on the tree itself.
+//! Trees from `asm::grammar` come numbered from 0 in preorder; trees built
+//! by hand are numbered with `Numbered::number`
+use asm::ast::*;
+use evm::opcode::Opcode;
+
+extern crate bigint;
+use self::bigint::U256;
+
+#[derive(Debug, Clone, Default)]
+pub struct Ids {
+  next: Nid,
+}
+
+impl Ids {
+  pub fn new() -> Ids {
+    Ids { next: 0 }
+  }
+
+  pub fn next(&mut self) -> Nid {
+    let id = self.next;
+    self.next += 1;
+    id
+  }
+}
+
+pub trait Numbered {
+  /// Gives each node of the tree the next id, parents before their children
+  fn number(&mut self, ids: &mut Ids);
+}
+
+impl<T: Numbered> Numbered for Node<T> {
+  fn number(&mut self, ids: &mut Ids) {
+    self.id = ids.next();
+    (**self).number(ids);
+  }
+}
+
+impl<T: Numbered> Numbered for Vec<T> {
+  fn number(&mut self, ids: &mut Ids) {
+    for item in self {
+      item.number(ids);
+    }
+  }
+}
+
+impl<T: Numbered> Numbered for Option<T> {
+  fn number(&mut self, ids: &mut Ids) {
+    if let Some(ref mut value) = *self {
+      value.number(ids);
+    }
+  }
+}
+
+macro_rules! numbered_values {
+  ($($t:ty),*) => {
+    $(
+      impl Numbered for $t {
+        fn number(&mut self, _: &mut Ids) {}
+      }
+    )*
+  }
+}
+
+macro_rules! numbered_struct {
+  ($t:ident { $($field:ident),* }) => {
+    impl Numbered for $t {
+      fn number(&mut self, ids: &mut Ids) {
+        $(self.$field.number(ids);)*
+      }
+    }
+  }
+}
+
+macro_rules! numbered_enum {
+  ($t:ident { $($variant:ident),* }) => {
+    impl Numbered for $t {
+      fn number(&mut self, ids: &mut Ids) {
+        match *self {
+          $($t::$variant(ref mut value) => value.number(ids),)*
+        }
+      }
+    }
+  }
+}
+
+numbered_values!(String, u8, usize, U256, Opcode, ControlOp, LiteralError);
+
+numbered_struct!(Object { code, items });
+numbered_enum!(ObjectItem { Object, Data });
+numbered_struct!(Data { value });
+
+numbered_struct!(Block { statements });
+numbered_enum!(Statement {
+  Block,
+  FunctionDefinition,
+  VariableDeclaration,
+  Assignment,
+  Expression,
+  If,
+  Switch,
+  ForLoop,
+  ControlOp,
+  SubAssembly,
+  LabelDefinition,
+  Instruction,
+  StackAssignment,
+  Error
+});
+numbered_enum!(Expression { Identifier, Literal, FunctionCall });
+
+numbered_struct!(FunctionDefinition { identifier, arguments, returns, body });
+numbered_struct!(VariableDeclaration { identifiers, expression });
+numbered_struct!(Assignment { identifiers, expression });
+numbered_struct!(Switch { expression, cases, default });
+numbered_struct!(Case { expression, block });
+numbered_struct!(If { condition, body });
+numbered_struct!(ForLoop { init, condition, post, body });
+numbered_struct!(SubAssembly { identifier, block });
+numbered_struct!(LabelDefinition { identifier, stack });
+numbered_enum!(LabelStack { Height, Items });
+numbered_struct!(Instruction { opcode });
+numbered_struct!(StackAssignment { identifier });
+numbered_struct!(SyntaxError { text });
+numbered_struct!(FunctionCall { identifier, arguments });
+numbered_struct!(Identifier { symbol });
+
+numbered_struct!(Literal { value });
+numbered_enum!(LiteralValue { HexNumber, DecNumber, StringLiteral, HexLiteral, Invalid });
+numbered_struct!(StringLiteral { bytes });
+numbered_struct!(HexLiteral { bytes });
+numbered_struct!(HexNumber { uint });
+numbered_struct!(DecNumber { uint });
+
+#[cfg(test)]
+use asm::grammar;
+
+#[test]
+fn it_numbers_parses_alike() {
+  let program = "{ function f(a) -> b { b := add(a, 1) } let x := f(2) }";
+
+  let first = grammar::block(program).unwrap();
+  grammar::block("{ let unrelated := 1 }").unwrap();
+  let second = grammar::block(program).unwrap();
+
+  assert_eq!(first, second);
+}
+
+#[test]
+fn it_numbers_in_preorder() {
+  let mut block = Block::new(vec![Node::new(Statement::Block(Block::new(vec![])))]);
+  block.number(&mut Ids::new());
+
+  assert_eq!(block.id, 0);
+  assert_eq!(block.statements[0].id, 1);
+  match *block.statements[0] {
+    Statement::Block(ref inner) => assert_eq!(inner.id, 2),
+    ref statement => panic!("expected a block, found {:?}", statement),
+  }
+}
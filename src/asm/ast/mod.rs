@@ -2,3 +2,5 @@ pub mod ast;
 pub use self::ast::*;
 
 pub mod visitor;
+pub mod structural;
+pub mod ids;
@@ -0,0 +1,228 @@
+//! Comparison and hashing of syntax trees by their structure alone, ignoring
+//! the ids and spans that differ between parses of the same program
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+extern crate bigint;
+use self::bigint::U256;
+
+use asm::ast::*;
+use evm::opcode::Opcode;
+
+pub trait Structural {
+  fn structurally_eq(&self, other: &Self) -> bool;
+
+  fn structural_hash<H: Hasher>(&self, state: &mut H);
+
+  /// Wraps the tree so that `==`, `assert_eq!` and hash maps compare it
+  /// structurally
+  fn structure<'a>(&'a self) -> Structure<'a, Self>
+    where Self: Sized
+  {
+    Structure(self)
+  }
+}
+
+pub struct Structure<'a, T: 'a>(pub &'a T);
+
+impl<'a, T: Structural> PartialEq for Structure<'a, T> {
+  fn eq(&self, other: &Structure<'a, T>) -> bool {
+    self.0.structurally_eq(other.0)
+  }
+}
+
+impl<'a, T: Structural> Eq for Structure<'a, T> {}
+
+impl<'a, T: Structural> Hash for Structure<'a, T> {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.0.structural_hash(state);
+  }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for Structure<'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    self.0.fmt(f)
+  }
+}
+
+impl<T: Structural> Structural for Node<T> {
+  fn structurally_eq(&self, other: &Node<T>) -> bool {
+    (**self).structurally_eq(&**other)
+  }
+
+  fn structural_hash<H: Hasher>(&self, state: &mut H) {
+    (**self).structural_hash(state);
+  }
+}
+
+impl<T: Structural> Structural for Vec<T> {
+  fn structurally_eq(&self, other: &Vec<T>) -> bool {
+    self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.structurally_eq(b))
+  }
+
+  fn structural_hash<H: Hasher>(&self, state: &mut H) {
+    self.len().hash(state);
+    for item in self {
+      item.structural_hash(state);
+    }
+  }
+}
+
+impl<T: Structural> Structural for Option<T> {
+  fn structurally_eq(&self, other: &Option<T>) -> bool {
+    match (self, other) {
+      (&Some(ref a), &Some(ref b)) => a.structurally_eq(b),
+      (&None, &None) => true,
+      _ => false,
+    }
+  }
+
+  fn structural_hash<H: Hasher>(&self, state: &mut H) {
+    match *self {
+      Some(ref value) => {
+        true.hash(state);
+        value.structural_hash(state);
+      }
+      None => false.hash(state),
+    }
+  }
+}
+
+/// Values without nodes in them are compared as they are
+macro_rules! structural_values {
+  ($($t:ty),*) => {
+    $(
+      impl Structural for $t {
+        fn structurally_eq(&self, other: &$t) -> bool {
+          self == other
+        }
+
+        fn structural_hash<H: Hasher>(&self, state: &mut H) {
+          self.hash(state);
+        }
+      }
+    )*
+  }
+}
+
+macro_rules! structural_struct {
+  ($t:ident { $($field:ident),* }) => {
+    impl Structural for $t {
+      fn structurally_eq(&self, other: &$t) -> bool {
+        true $(&& self.$field.structurally_eq(&other.$field))*
+      }
+
+      fn structural_hash<H: Hasher>(&self, state: &mut H) {
+        $(self.$field.structural_hash(state);)*
+      }
+    }
+  }
+}
+
+macro_rules! structural_enum {
+  ($t:ident { $($variant:ident),* }) => {
+    impl Structural for $t {
+      fn structurally_eq(&self, other: &$t) -> bool {
+        match (self, other) {
+          $((&$t::$variant(ref a), &$t::$variant(ref b)) => a.structurally_eq(b),)*
+          _ => false,
+        }
+      }
+
+      fn structural_hash<H: Hasher>(&self, state: &mut H) {
+        match *self {
+          $($t::$variant(ref value) => {
+            stringify!($variant).hash(state);
+            value.structural_hash(state);
+          })*
+        }
+      }
+    }
+  }
+}
+
+structural_values!(String, u8, usize, U256, Opcode, ControlOp, LiteralError);
+
+structural_struct!(Object { name, code, items });
+structural_enum!(ObjectItem { Object, Data });
+structural_struct!(Data { name, value });
+
+structural_struct!(Block { statements });
+structural_enum!(Statement {
+  Block,
+  FunctionDefinition,
+  VariableDeclaration,
+  Assignment,
+  Expression,
+  If,
+  Switch,
+  ForLoop,
+  ControlOp,
+  SubAssembly,
+  LabelDefinition,
+  Instruction,
+  StackAssignment,
+  Error
+});
+structural_enum!(Expression { Identifier, Literal, FunctionCall });
+
+structural_struct!(FunctionDefinition { identifier, arguments, returns, body });
+structural_struct!(VariableDeclaration { identifiers, expression });
+structural_struct!(Assignment { identifiers, expression });
+structural_struct!(Switch { expression, cases, default });
+structural_struct!(Case { expression, block });
+structural_struct!(If { condition, body });
+structural_struct!(ForLoop { init, condition, post, body });
+structural_struct!(SubAssembly { identifier, block });
+structural_struct!(LabelDefinition { identifier, stack });
+structural_enum!(LabelStack { Height, Items });
+structural_struct!(Instruction { opcode });
+structural_struct!(StackAssignment { identifier });
+structural_struct!(SyntaxError { text });
+structural_struct!(FunctionCall { identifier, arguments });
+structural_struct!(Identifier { symbol, type_name });
+
+structural_struct!(Literal { value, type_name });
+structural_enum!(LiteralValue { HexNumber, DecNumber, StringLiteral, HexLiteral, Invalid });
+structural_struct!(StringLiteral { bytes });
+structural_struct!(HexLiteral { bytes });
+structural_struct!(HexNumber { uint, digits });
+structural_struct!(DecNumber { uint, digits });
+
+#[cfg(test)]
+use std::collections::HashSet;
+
+#[cfg(test)]
+use asm::grammar;
+
+#[test]
+fn it_ignores_ids_and_spans() {
+  let a = grammar::block("{ let x := add(1, y) }").unwrap();
+  let b = grammar::block("{\n  let x := /* one */ add(1,y)\n}").unwrap();
+
+  assert!(a != b);
+  assert_eq!(a.structure(), b.structure());
+}
+
+#[test]
+fn it_tells_different_programs_apart() {
+  let programs = ["{ let x := 1 }",
+                  "{ let x := 0x1 }",
+                  "{ let x:u256 := 1 }",
+                  "{ let y := 1 }",
+                  "{ x := 1 }",
+                  "{ let (x, y) := 1 }",
+                  "{ { let x := 1 } }",
+                  "{ }"];
+  let blocks: Vec<_> = programs.iter().map(|p| grammar::block(p).unwrap()).collect();
+
+  for (i, a) in blocks.iter().enumerate() {
+    for (j, b) in blocks.iter().enumerate() {
+      assert_eq!(a.structurally_eq(b), i == j, "{} and {}", programs[i], programs[j]);
+    }
+  }
+
+  let reparsed: Vec<_> = programs.iter().map(|p| grammar::block(p).unwrap()).collect();
+  let structures: HashSet<_> = blocks.iter().chain(&reparsed).map(|b| b.structure()).collect();
+  assert_eq!(structures.len(), programs.len());
+}
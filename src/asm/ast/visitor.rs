@@ -4,6 +4,14 @@ pub trait Visitor<'v>: Sized {
   fn push(&mut self, _: Nid) {}
   fn pop(&mut self) {}
 
+  fn visit_object(&mut self, o: &'v Node<Object>) {
+    &o.walk(self);
+  }
+
+  fn visit_data(&mut self, d: &'v Node<Data>) {
+    &d.walk(self);
+  }
+
   fn visit_block(&mut self, b: &'v Node<Block>) {
     &b.walk(self);
   }
@@ -28,6 +36,10 @@ pub trait Visitor<'v>: Sized {
     &a.walk(self);
   }
 
+  fn visit_if(&mut self, i: &'v Node<If>) {
+    &i.walk(self);
+  }
+
   fn visit_switch(&mut self, s: &'v Node<Switch>) {
     &s.walk(self);
   }
@@ -48,6 +60,22 @@ pub trait Visitor<'v>: Sized {
     &a.walk(self);
   }
 
+  fn visit_label_definition(&mut self, l: &'v Node<LabelDefinition>) {
+    &l.walk(self);
+  }
+
+  fn visit_instruction(&mut self, i: &'v Node<Instruction>) {
+    &i.walk(self);
+  }
+
+  fn visit_stack_assignment(&mut self, a: &'v Node<StackAssignment>) {
+    &a.walk(self);
+  }
+
+  fn visit_syntax_error(&mut self, e: &'v Node<SyntaxError>) {
+    &e.walk(self);
+  }
+
   fn visit_function_call(&mut self, c: &'v Node<FunctionCall>) {
     &c.walk(self);
   }
@@ -75,12 +103,38 @@ pub trait Visitor<'v>: Sized {
   fn visit_dec_number(&mut self, n: &'v Node<DecNumber>) {
     &n.walk(self);
   }
+
+  fn visit_invalid_literal(&mut self, e: &'v Node<LiteralError>) {
+    &e.walk(self);
+  }
 }
 
 pub trait Walkable<'w> {
   fn walk<V: Visitor<'w>>(&'w self, _: &mut V) {}
 }
 
+impl<'w> Walkable<'w> for Node<Object> {
+  fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
+    visitor.push(self.id);
+    visitor.visit_block(&self.code);
+    for item in &self.items {
+      match **item {
+        ObjectItem::Object(ref node) => visitor.visit_object(node),
+        ObjectItem::Data(ref node) => visitor.visit_data(node),
+      }
+    }
+    visitor.pop();
+  }
+}
+
+impl<'w> Walkable<'w> for Node<Data> {
+  fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
+    visitor.push(self.id);
+    visitor.visit_literal(&self.value);
+    visitor.pop();
+  }
+}
+
 impl<'w> Walkable<'w> for Node<Block> {
   fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
     visitor.push(self.id);
@@ -100,10 +154,15 @@ impl<'w> Walkable<'w> for Node<Statement> {
       Statement::VariableDeclaration(ref node) => visitor.visit_variable_declaration(node),
       Statement::Assignment(ref node) => visitor.visit_assignment(node),
       Statement::Expression(ref node) => visitor.visit_expression(node),
+      Statement::If(ref node) => visitor.visit_if(node),
       Statement::Switch(ref node) => visitor.visit_switch(node),
       Statement::ForLoop(ref node) => visitor.visit_for_loop(node),
       Statement::ControlOp(ref node) => visitor.visit_control_op(node),
       Statement::SubAssembly(ref node) => visitor.visit_sub_assembly(node),
+      Statement::LabelDefinition(ref node) => visitor.visit_label_definition(node),
+      Statement::Instruction(ref node) => visitor.visit_instruction(node),
+      Statement::StackAssignment(ref node) => visitor.visit_stack_assignment(node),
+      Statement::Error(ref node) => visitor.visit_syntax_error(node),
     }
     visitor.pop();
   }
@@ -180,6 +239,19 @@ impl<'w> Walkable<'w> for Node<Assignment> {
   }
 }
 
+impl<'w> Walkable<'w> for Node<If> {
+  fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
+    visitor.push(self.id);
+    match **self {
+      If { ref condition, ref body } => {
+        visitor.visit_expression(condition);
+        visitor.visit_block(body);
+      }
+    }
+    visitor.pop();
+  }
+}
+
 impl<'w> Walkable<'w> for Node<Switch> {
   fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
     visitor.push(self.id);
@@ -244,6 +316,35 @@ impl<'w> Walkable<'w> for Node<SubAssembly> {
   }
 }
 
+impl<'w> Walkable<'w> for Node<LabelDefinition> {
+  fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
+    visitor.push(self.id);
+    match **self {
+      LabelDefinition { ref identifier, ref stack } => {
+        visitor.visit_identifier(identifier);
+        if let Some(LabelStack::Items(ref items)) = *stack {
+          for item in items {
+            visitor.visit_identifier(item);
+          }
+        }
+      }
+    }
+    visitor.pop();
+  }
+}
+
+impl<'w> Walkable<'w> for Node<StackAssignment> {
+  fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
+    visitor.push(self.id);
+    match **self {
+      StackAssignment { ref identifier } => {
+        visitor.visit_identifier(identifier);
+      }
+    }
+    visitor.pop();
+  }
+}
+
 impl<'w> Walkable<'w> for Node<FunctionCall> {
   fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
     visitor.push(self.id);
@@ -262,11 +363,12 @@ impl<'w> Walkable<'w> for Node<FunctionCall> {
 impl<'w> Walkable<'w> for Node<Literal> {
   fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
     visitor.push(self.id);
-    match **self {
-      Literal::HexNumber(ref node) => visitor.visit_hex_number(node),
-      Literal::DecNumber(ref node) => visitor.visit_dec_number(node),
-      Literal::StringLiteral(ref node) => visitor.visit_string_literal(node),
-      Literal::HexLiteral(ref node) => visitor.visit_hex_literal(node),
+    match self.value {
+      LiteralValue::HexNumber(ref node) => visitor.visit_hex_number(node),
+      LiteralValue::DecNumber(ref node) => visitor.visit_dec_number(node),
+      LiteralValue::StringLiteral(ref node) => visitor.visit_string_literal(node),
+      LiteralValue::HexLiteral(ref node) => visitor.visit_hex_literal(node),
+      LiteralValue::Invalid(ref node) => visitor.visit_invalid_literal(node),
     }
     visitor.pop();
   }
@@ -300,6 +402,13 @@ impl<'w> Walkable<'w> for Node<StringLiteral> {
   }
 }
 
+impl<'w> Walkable<'w> for Node<LiteralError> {
+  fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
+    visitor.push(self.id);
+    visitor.pop();
+  }
+}
+
 impl<'w> Walkable<'w> for Node<Identifier> {
   fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
     visitor.push(self.id);
@@ -307,6 +416,20 @@ impl<'w> Walkable<'w> for Node<Identifier> {
   }
 }
 
+impl<'w> Walkable<'w> for Node<Instruction> {
+  fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
+    visitor.push(self.id);
+    visitor.pop();
+  }
+}
+
+impl<'w> Walkable<'w> for Node<SyntaxError> {
+  fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
+    visitor.push(self.id);
+    visitor.pop();
+  }
+}
+
 impl<'w> Walkable<'w> for Node<ControlOp> {
   fn walk<V: Visitor<'w>>(&'w self, visitor: &mut V) {
     visitor.push(self.id);
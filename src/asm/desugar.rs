@@ -0,0 +1,819 @@
+//! Lowering of functions, loops, switches and ifs into labels and jumps
+//!
+//! Follows the desugaring in the Solidity assembly specification, using the
+//! calling convention of `evm::codegen`: the caller pushes a return label and
+//! the arguments (first argument on top) and jumps to the function's label,
+//! which leaves only the return values behind. Labels that are only jumped to
+//! say what is on the stack there, such as `f[$function0_return, b, a]:` for
+//! the return label and arguments a function starts with, so the lowered
+//! program keeps the stack layout and compiles to the same code.
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use asm::ast::*;
+use asm::ast::ids::{Ids, Numbered};
+use asm::ast::visitor::*;
+use evm::codegen;
+use evm::opcode::Opcode;
+
+#[derive(Debug, Clone)]
+struct Loop {
+  next: String,
+  end: String,
+  height: usize,
+}
+
+/// Where `leave` jumps to in the function being lowered, i.e. the code that
+/// returns from it
+#[derive(Debug, Clone)]
+struct Exit {
+  label: String,
+  height: usize,
+  /// The label is only placed when jumped to
+  used: bool,
+}
+
+pub struct Desugarer {
+  /// Every symbol in the program, for generated names to stay clear of
+  symbols: HashSet<String>,
+  counters: HashMap<&'static str, usize>,
+  /// Number of values returned by each function in scope
+  functions: Vec<HashMap<String, usize>>,
+  /// Stack height in the function being lowered, as `evm::codegen` counts it
+  height: usize,
+  /// Height of the variables in scope, below any other values on the stack
+  base: usize,
+  loops: Vec<Loop>,
+  exit: Option<Exit>,
+}
+
+impl Desugarer {
+  pub fn desugar(block: &Node<Block>) -> Node<Block> {
+    let mut collector = SymbolCollector { symbols: HashSet::new() };
+    collector.visit_block(block);
+
+    let mut desugarer = Desugarer {
+      symbols: collector.symbols,
+      counters: HashMap::new(),
+      functions: vec![],
+      height: 0,
+      base: 0,
+      loops: vec![],
+      exit: None,
+    };
+
+    let mut lowered = desugarer.block(block);
+    lowered.number(&mut Ids::new());
+    lowered
+  }
+
+  /// Next index `i` for which no symbol starts with `$<kind><i>_`
+  fn fresh(&mut self, kind: &'static str) -> usize {
+    let mut index = *self.counters.get(kind).unwrap_or(&0);
+    loop {
+      let prefix = format!("${}{}_", kind, index);
+      if !self.symbols.iter().any(|symbol| symbol.starts_with(&prefix)) {
+        break;
+      }
+      index += 1;
+    }
+
+    self.counters.insert(kind, index + 1);
+    index
+  }
+
+  /// Functions are visible throughout the block they are defined in,
+  /// including before their definition
+  fn enter(&mut self, statements: &[Node<Statement>]) {
+    let mut functions = HashMap::new();
+    for statement in statements {
+      if let Statement::FunctionDefinition(ref definition) = **statement {
+        let returns = definition.returns.as_ref().map_or(0, |returns| returns.len());
+        functions.insert(definition.identifier.symbol.clone(), returns);
+      }
+    }
+
+    self.functions.push(functions);
+  }
+
+  fn is_function(&self, symbol: &str) -> bool {
+    self.functions.iter().any(|scope| scope.contains_key(symbol))
+  }
+
+  fn returns(&self, symbol: &str) -> usize {
+    self.functions.iter().rev().filter_map(|scope| scope.get(symbol)).next().cloned().unwrap_or(0)
+  }
+
+  /// Whether evaluating the expression calls a function defined in the
+  /// program, which takes precedence over a builtin of the same name
+  fn calls_function(&self, e: &Node<Expression>) -> bool {
+    match **e {
+      Expression::FunctionCall(ref c) => {
+        self.is_function(&c.identifier.symbol) ||
+        c.arguments.iter().any(|argument| self.calls_function(argument))
+      }
+      _ => false,
+    }
+  }
+
+  /// Number of values the expression leaves on the stack
+  fn values(&self, e: &Node<Expression>) -> usize {
+    match **e {
+      Expression::FunctionCall(ref c) if self.is_function(&c.identifier.symbol) => {
+        self.returns(&c.identifier.symbol)
+      }
+      Expression::FunctionCall(ref c) => {
+        Opcode::builtin(&c.identifier.symbol).map_or(0, |op| op.outputs())
+      }
+      _ => 1,
+    }
+  }
+
+  fn block(&mut self, b: &Node<Block>) -> Node<Block> {
+    self.labelled_block(b, 0)
+  }
+
+  /// Lowers a block, the given number of values on top of the stack having
+  /// been declared in it by the label before it
+  fn labelled_block(&mut self, b: &Node<Block>, items: usize) -> Node<Block> {
+    let height = self.height - items;
+    let base = mem::replace(&mut self.base, self.height);
+    self.enter(&b.statements);
+
+    let statements = self.statements(&b.statements);
+
+    self.functions.pop();
+    self.height = height;
+    self.base = base;
+    Block::new(statements).with_span(b.span)
+  }
+
+  fn statements(&mut self, statements: &[Node<Statement>]) -> Vec<Node<Statement>> {
+    let mut out = vec![];
+    let mut items = None;
+    for (i, statement) in statements.iter().enumerate() {
+      match (items.take(), &**statement) {
+        (Some(items), &Statement::Block(ref b)) => {
+          out.push(Node::new(Statement::Block(self.labelled_block(b, items))));
+          continue;
+        }
+        // without a block to go in, the label's variables stay in this one
+        (Some(_), _) => self.base = self.height,
+        (None, _) => {}
+      }
+
+      self.statement(statement, statements.get(i + 1), &mut out);
+      if let Statement::LabelDefinition(ref label) = **statement {
+        items = label.items().map(|items| items.len());
+      }
+    }
+
+    out
+  }
+
+  fn statement(&mut self,
+               s: &Node<Statement>,
+               next: Option<&Node<Statement>>,
+               out: &mut Vec<Node<Statement>>) {
+    match **s {
+      Statement::Block(ref b) => out.push(Node::new(Statement::Block(self.block(b)))),
+      Statement::FunctionDefinition(ref f) => self.function_definition(f, out),
+      Statement::VariableDeclaration(ref v) => {
+        let binds = next.map_or(true, |next| !starts_with_block(next));
+        self.variable_declaration(v, binds, out)
+      }
+      Statement::Assignment(ref a) => self.assignment(a, out),
+      Statement::Expression(ref e) => self.expression(e, out),
+      Statement::If(ref i) => self.if_statement(i, out),
+      Statement::Switch(ref s) => self.switch(s, out),
+      Statement::ForLoop(ref f) => self.for_loop(f, out),
+      Statement::ControlOp(ref o) => self.control_op(o, next.is_none(), out),
+      Statement::SubAssembly(ref a) => {
+        let height = mem::replace(&mut self.height, 0);
+        let base = mem::replace(&mut self.base, 0);
+        let loops = mem::replace(&mut self.loops, vec![]);
+        let exit = mem::replace(&mut self.exit, None);
+
+        let block = self.block(&a.block);
+        let assembly = SubAssembly::new(a.identifier.clone(), block).with_span(a.span);
+        out.push(Node::new(Statement::SubAssembly(assembly)));
+
+        self.height = height;
+        self.base = base;
+        self.loops = loops;
+        self.exit = exit;
+      }
+      Statement::LabelDefinition(ref l) => {
+        if let Some(ref stack) = l.stack {
+          self.height = self.base + stack.len();
+        }
+        out.push(s.clone());
+      }
+      Statement::Instruction(ref i) => {
+        self.height = (self.height + i.opcode.outputs()).saturating_sub(i.opcode.inputs());
+        out.push(s.clone());
+      }
+      Statement::StackAssignment(_) => {
+        self.height = self.height.saturating_sub(1);
+        out.push(s.clone());
+      }
+      Statement::Error(_) => out.push(s.clone()),
+    }
+  }
+
+  /// ```text
+  /// jump($functionI_end)
+  /// f[$functionI_return, argn, ..., arg1]:
+  /// {
+  ///   let r := 0
+  ///   { body }
+  ///   $functionI_exit:
+  ///   swap and pop down to the return values and return label
+  ///   jump
+  /// }
+  /// $functionI_end:
+  /// ```
+  fn function_definition(&mut self, f: &Node<FunctionDefinition>, out: &mut Vec<Node<Statement>>) {
+    let FunctionDefinition { ref identifier, ref arguments, ref returns, ref body } = **f;
+    let returns = match *returns {
+      Some(ref returns) => returns.clone(),
+      None => vec![],
+    };
+
+    let index = self.fresh("function");
+    let end = format!("$function{}_end", index);
+    out.push(call_statement("jump", vec![reference(&end)]));
+
+    // the return label and arguments pushed by the caller
+    let mut items = vec![Identifier::new(&format!("$function{}_return", index))];
+    items.extend(arguments.iter().rev().cloned());
+    out.push(label_with_stack(&identifier.symbol, LabelStack::Items(items)));
+
+    let height = mem::replace(&mut self.height, 1 + arguments.len());
+    let base = mem::replace(&mut self.base, self.height);
+    let loops = mem::replace(&mut self.loops, vec![]);
+
+    let mut statements = vec![];
+    for identifier in &returns {
+      let declaration = VariableDeclaration::new(vec![identifier.clone()], zero());
+      statements.push(Node::new(Statement::VariableDeclaration(declaration)));
+    }
+    self.height += returns.len();
+    self.base = self.height;
+
+    let exit = Exit {
+      label: format!("$function{}_exit", index),
+      height: self.height,
+      used: false,
+    };
+    let outer_exit = mem::replace(&mut self.exit, Some(exit));
+
+    statements.push(Node::new(Statement::Block(self.block(body))));
+    match mem::replace(&mut self.exit, outer_exit) {
+      Some(Exit { ref label, used: true, .. }) => statements.push(self::label(label)),
+      _ => {}
+    }
+
+    for step in codegen::return_shuffle(arguments.len(), returns.len()) {
+      statements.push(instruction(match step {
+        Some(depth) => Opcode::swap(depth),
+        None => Opcode::POP,
+      }));
+    }
+    statements.push(instruction(Opcode::JUMP));
+    out.push(Node::new(Statement::Block(Block::new(statements).with_span(body.span))));
+
+    self.height = height;
+    self.base = base;
+    self.loops = loops;
+
+    // values on the stack besides the variables are back once skipped past
+    match self.height - self.base {
+      0 => out.push(label(&end)),
+      values => out.push(label_with_stack(&end, LabelStack::Height(values))),
+    }
+  }
+
+  /// Where the variables can be declared by the label a function returns
+  /// to, i.e. `$funcallI_return[x, y]:`, it is left to do so. Otherwise
+  /// they are declared up front and assigned from the stack:
+  ///
+  /// ```text
+  /// let x := 0
+  /// let y := 0
+  /// expression
+  /// =: y
+  /// =: x
+  /// ```
+  fn variable_declaration(&mut self,
+                          v: &Node<VariableDeclaration>,
+                          binds: bool,
+                          out: &mut Vec<Node<Statement>>) {
+    let VariableDeclaration { ref identifiers, ref expression } = **v;
+    let height = self.height;
+
+    match **expression {
+      _ if !self.calls_function(expression) => {
+        out.push(Node::new(Statement::VariableDeclaration(v.clone())));
+      }
+      Expression::FunctionCall(ref c) if binds && self.height == self.base &&
+                                         self.is_function(&c.identifier.symbol) => {
+        let ret = self.call(c, out);
+        out.push(label_with_stack(&ret, LabelStack::Items(identifiers.clone())));
+      }
+      _ => {
+        for identifier in identifiers {
+          let declaration = VariableDeclaration::new(vec![identifier.clone()], zero());
+          out.push(Node::new(Statement::VariableDeclaration(declaration)));
+        }
+        self.height += identifiers.len();
+        self.base = self.height;
+
+        self.flatten(expression, out);
+        for identifier in identifiers.iter().rev() {
+          let assignment = StackAssignment::new(identifier.clone());
+          out.push(Node::new(Statement::StackAssignment(assignment)));
+        }
+      }
+    }
+
+    self.height = height + identifiers.len();
+    self.base = self.height;
+  }
+
+  fn assignment(&mut self, a: &Node<Assignment>, out: &mut Vec<Node<Statement>>) {
+    let Assignment { ref identifiers, ref expression } = **a;
+
+    if !self.calls_function(expression) {
+      out.push(Node::new(Statement::Assignment(a.clone())));
+      return;
+    }
+
+    let height = self.height;
+    self.flatten(expression, out);
+    for identifier in identifiers.iter().rev() {
+      let assignment = StackAssignment::new(identifier.clone());
+      out.push(Node::new(Statement::StackAssignment(assignment)));
+    }
+    self.height = height;
+  }
+
+  fn expression(&mut self, e: &Node<Expression>, out: &mut Vec<Node<Statement>>) {
+    if self.calls_function(e) {
+      self.flatten(e, out);
+    } else {
+      out.push(Node::new(Statement::Expression(e.clone())));
+      self.height += self.values(e);
+    }
+  }
+
+  /// Turns an expression calling functions into a stream of statements that
+  /// leaves its values on the stack, in the order `evm::codegen` evaluates
+  /// it
+  fn flatten(&mut self, e: &Node<Expression>, out: &mut Vec<Node<Statement>>) {
+    let c = match **e {
+      Expression::FunctionCall(ref c) if self.calls_function(e) => c,
+      _ => return self.expression(e, out),
+    };
+
+    match Opcode::builtin(&c.identifier.symbol) {
+      Some(op) if !self.is_function(&c.identifier.symbol) => {
+        for argument in c.arguments.iter().rev() {
+          self.flatten(argument, out);
+        }
+        out.push(instruction(op));
+        self.height = self.height + op.outputs() - op.inputs();
+      }
+      _ => {
+        let ret = self.call(c, out);
+        let values = self.height - self.base;
+        out.push(label_with_stack(&ret, LabelStack::Height(values)));
+      }
+    }
+  }
+
+  /// Calls a function, leaving it to the caller to place the label it
+  /// returns to
+  ///
+  /// ```text
+  /// $funcallI_return argn ... arg1 jump(f)
+  /// ```
+  fn call(&mut self, c: &Node<FunctionCall>, out: &mut Vec<Node<Statement>>) -> String {
+    let ret = format!("$funcall{}_return", self.fresh("funcall"));
+    let height = self.height;
+
+    out.push(Node::new(Statement::Expression(reference(&ret))));
+    self.height += 1;
+    for argument in c.arguments.iter().rev() {
+      self.flatten(argument, out);
+    }
+    out.push(call_statement("jump", vec![reference(&c.identifier.symbol)]));
+
+    self.height = height + self.returns(&c.identifier.symbol);
+    ret
+  }
+
+  /// ```text
+  /// jumpi($ifI_end, iszero(condition))
+  /// { body }
+  /// $ifI_end:
+  /// ```
+  fn if_statement(&mut self, i: &Node<If>, out: &mut Vec<Node<Statement>>) {
+    let If { ref condition, ref body } = **i;
+
+    let end = format!("$if{}_end", self.fresh("if"));
+    let condition = call("iszero", vec![condition.clone()]);
+    self.expression(&call("jumpi", vec![reference(&end), condition]), out);
+    out.push(Node::new(Statement::Block(self.block(body))));
+    out.push(label(&end));
+  }
+
+  /// ```text
+  /// {
+  ///   let $switchI_value := expression (or just expression, without cases)
+  ///   jumpi($switchI_caseJ, eq($switchI_value, value)) (for each case)
+  ///   { default }
+  ///   jump($switchI_end)
+  ///   $switchI_caseJ: { body } jump($switchI_end) (for each case)
+  ///   $switchI_end:
+  /// }
+  /// ```
+  fn switch(&mut self, s: &Node<Switch>, out: &mut Vec<Node<Statement>>) {
+    let Switch { ref expression, ref cases, ref default } = **s;
+
+    let index = self.fresh("switch");
+    let value = format!("$switch{}_value", index);
+    let end = format!("$switch{}_end", index);
+    let labels: Vec<String> =
+      (0..cases.len()).map(|j| format!("$switch{}_case{}", index, j)).collect();
+
+    let height = self.height;
+    let base = mem::replace(&mut self.base, height);
+    let mut statements = vec![];
+
+    if cases.is_empty() {
+      // nothing compares against the value, which is only left on the stack
+      self.expression(expression, &mut statements);
+    } else {
+      let declaration = VariableDeclaration::new(vec![Identifier::new(&value)],
+                                                 expression.clone());
+      self.variable_declaration(&declaration, true, &mut statements);
+    }
+
+    for (case, label) in cases.iter().zip(&labels) {
+      let condition = call("eq", vec![reference(&value), case.expression.clone()]);
+      self.expression(&call("jumpi", vec![reference(label), condition]), &mut statements);
+    }
+
+    if let Some(ref block) = *default {
+      statements.push(Node::new(Statement::Block(self.block(block))));
+    }
+    statements.push(call_statement("jump", vec![reference(&end)]));
+
+    for (case, label) in cases.iter().zip(&labels) {
+      statements.push(self::label(label));
+      statements.push(Node::new(Statement::Block(self.block(&case.block))));
+      statements.push(call_statement("jump", vec![reference(&end)]));
+    }
+    statements.push(label(&end));
+
+    self.height = height;
+    self.base = base;
+    out.push(Node::new(Statement::Block(Block::new(statements).with_span(s.span))));
+  }
+
+  /// ```text
+  /// {
+  ///   init
+  ///   $forI_begin:
+  ///   jumpi($forI_end, iszero(condition))
+  ///   { body }
+  ///   $forI_continue:
+  ///   { post }
+  ///   jump($forI_begin)
+  ///   $forI_end:
+  /// }
+  /// ```
+  fn for_loop(&mut self, f: &Node<ForLoop>, out: &mut Vec<Node<Statement>>) {
+    let ForLoop { ref init, ref condition, ref post, ref body } = **f;
+
+    let index = self.fresh("for");
+    let begin = format!("$for{}_begin", index);
+    let next = format!("$for{}_continue", index);
+    let end = format!("$for{}_end", index);
+
+    // variables declared in the init block stay in scope for the whole loop
+    let height = self.height;
+    let base = mem::replace(&mut self.base, height);
+    self.enter(&init.statements);
+    let mut statements = self.statements(&init.statements);
+
+    statements.push(label(&begin));
+    let condition = call("iszero", vec![condition.clone()]);
+    self.expression(&call("jumpi", vec![reference(&end), condition]),
+                    &mut statements);
+
+    self.loops.push(Loop {
+                      next: next.clone(),
+                      end: end.clone(),
+                      height: self.height,
+                    });
+    statements.push(Node::new(Statement::Block(self.block(body))));
+    self.loops.pop();
+
+    statements.push(label(&next));
+    statements.push(Node::new(Statement::Block(self.block(post))));
+    statements.push(call_statement("jump", vec![reference(&begin)]));
+    statements.push(label(&end));
+
+    self.functions.pop();
+    self.height = height;
+    self.base = base;
+    out.push(Node::new(Statement::Block(Block::new(statements).with_span(f.span))));
+  }
+
+  /// ```text
+  /// pop (for each value pushed since the target)
+  /// jump(target)
+  /// ```
+  ///
+  /// enclosed in a block of its own if code follows, which is then compiled
+  /// with the popped values still on the stack
+  fn control_op(&mut self, o: &Node<ControlOp>, last: bool, out: &mut Vec<Node<Statement>>) {
+    let target = match (&**o, self.loops.last(), self.exit.as_mut()) {
+      (&ControlOp::Break, Some(target), _) => Some((target.end.clone(), target.height)),
+      (&ControlOp::Continue, Some(target), _) => Some((target.next.clone(), target.height)),
+      (&ControlOp::Leave, _, Some(exit)) => {
+        exit.used = true;
+        Some((exit.label.clone(), exit.height))
+      }
+      _ => None,
+    };
+
+    match target {
+      Some((label, height)) => {
+        let values = self.height - height;
+        let mut statements: Vec<_> = (0..values).map(|_| instruction(Opcode::POP)).collect();
+        statements.push(call_statement("jump", vec![reference(&label)]));
+
+        if values == 0 || last {
+          out.extend(statements);
+        } else {
+          out.push(Node::new(Statement::Block(Block::new(statements))));
+        }
+      }
+      // misplaced, as analysis would have reported
+      None => out.push(Node::new(Statement::ControlOp(o.clone()))),
+    }
+  }
+}
+
+/// Whether the statement is lowered into statements starting with a block,
+/// which would take in the variables of a label placed right before it.
+/// Control ops are counted in, as they are when they pop values and code
+/// follows them
+fn starts_with_block(s: &Statement) -> bool {
+  match *s {
+    Statement::Block(_) |
+    Statement::Switch(_) |
+    Statement::ForLoop(_) |
+    Statement::ControlOp(_) => true,
+    _ => false,
+  }
+}
+
+fn reference(symbol: &str) -> Node<Expression> {
+  Node::new(Expression::Identifier(Identifier::new(symbol)))
+}
+
+fn zero() -> Node<Expression> {
+  let number = DecNumber::new("0").unwrap();
+  Node::new(Expression::Literal(Literal::new(LiteralValue::DecNumber(number), None)))
+}
+
+fn call(symbol: &str, arguments: Vec<Node<Expression>>) -> Node<Expression> {
+  Node::new(Expression::FunctionCall(FunctionCall::new(Identifier::new(symbol), arguments)))
+}
+
+fn call_statement(symbol: &str, arguments: Vec<Node<Expression>>) -> Node<Statement> {
+  Node::new(Statement::Expression(call(symbol, arguments)))
+}
+
+fn label(symbol: &str) -> Node<Statement> {
+  Node::new(Statement::LabelDefinition(LabelDefinition::new(Identifier::new(symbol))))
+}
+
+fn label_with_stack(symbol: &str, stack: LabelStack) -> Node<Statement> {
+  let definition = LabelDefinition::with_stack(Identifier::new(symbol), stack);
+  Node::new(Statement::LabelDefinition(definition))
+}
+
+fn instruction(op: Opcode) -> Node<Statement> {
+  Node::new(Statement::Instruction(Instruction::new(op)))
+}
+
+struct SymbolCollector {
+  symbols: HashSet<String>,
+}
+
+impl<'v> Visitor<'v> for SymbolCollector {
+  fn visit_identifier(&mut self, i: &'v Node<Identifier>) {
+    self.symbols.insert(i.symbol.clone());
+  }
+}
+
+#[cfg(test)]
+use asm::grammar;
+
+#[cfg(test)]
+use asm::pretty::PrettyPrinter;
+
+#[cfg(test)]
+use asm::ast::structural::Structural;
+
+#[cfg(test)]
+use config::Config;
+
+#[cfg(test)]
+use process::Processor;
+
+#[cfg(test)]
+fn compile(program: &str) -> Vec<u8> {
+  let mut config = Config::new();
+  config.source_str(program);
+
+  Processor::new()
+    .configure(config)
+    .ok()
+    .and_then(|p| p.parse().ok())
+    .and_then(|p| p.analyze().ok())
+    .and_then(|p| p.assemble().ok())
+    .expect(program)
+    .bytecode()
+}
+
+#[cfg(test)]
+fn desugar(program: &str) -> String {
+  let block = grammar::block(program).unwrap();
+  let lowered = Desugarer::desugar(&block);
+
+  let mut out = Vec::new();
+  PrettyPrinter::print(&lowered, &mut out);
+  let printed = String::from_utf8(out).unwrap();
+
+  // the lowered program is itself valid assembly, compiling to the same code
+  let reparsed = grammar::block(&printed).unwrap();
+  assert_eq!(reparsed.structure(), lowered.structure());
+  assert_eq!(compile(&printed), compile(program));
+
+  printed
+}
+
+#[test]
+fn it_lowers_loops_and_switches() {
+  let program = "{ let n := 2 for { let i := 0 } lt(i, n) { i := add(i, 1) } { if i { break } } }";
+  assert_eq!(desugar(program),
+             r#"{
+  let n := 2
+  {
+    let i := 0
+    $for0_begin:
+    jumpi($for0_end, iszero(lt(i, n)))
+    {
+      jumpi($if0_end, iszero(i))
+      { jump($for0_end) }
+      $if0_end:
+    }
+    $for0_continue:
+    { i := add(i, 1) }
+    jump($for0_begin)
+    $for0_end:
+  }
+}"#);
+
+  let program = "{ switch calldataload(0) case 1: { let x := 2 } default: { stop() } stop() }";
+  assert_eq!(desugar(program),
+             r#"{
+  {
+    let $switch0_value := calldataload(0)
+    jumpi($switch0_case0, eq($switch0_value, 1))
+    { stop() }
+    jump($switch0_end)
+    $switch0_case0:
+    { let x := 2 }
+    jump($switch0_end)
+    $switch0_end:
+  }
+  stop()
+}"#);
+}
+
+#[test]
+fn it_lowers_functions() {
+  let program = "{ function f(a) -> b { let c := a if c { leave } b := c } let x := f(7) }";
+  assert_eq!(desugar(program),
+             r#"{
+  jump($function0_end)
+  f[$function0_return, a]:
+  {
+    let b := 0
+    {
+      let c := a
+      jumpi($if0_end, iszero(c))
+      {
+        pop
+        jump($function0_exit)
+      }
+      $if0_end:
+      b := c
+    }
+    $function0_exit:
+    swap2
+    swap1
+    pop
+    jump
+  }
+  $function0_end:
+  $funcall0_return
+  7
+  jump(f)
+  $funcall0_return[x]:
+}"#);
+
+  // calls within expressions are turned into statements
+  assert_eq!(desugar("{ function f() -> r { } mstore(0, add(f(), 1)) }"),
+             r#"{
+  jump($function0_end)
+  f[$function0_return]:
+  {
+    let r := 0
+    { }
+    swap1
+    jump
+  }
+  $function0_end:
+  1
+  $funcall0_return
+  jump(f)
+  $funcall0_return[2]:
+  add
+  0
+  mstore
+}"#);
+}
+
+#[test]
+fn it_lowers_function_arguments_and_returns() {
+  let program = r#"{
+    function f(a, b) -> (x, y) {
+      x := add(a, b)
+      y := mul(g(a), b)
+    }
+    function g(a) -> r { r := a }
+    let p, q := f(1, 2)
+    sstore(p, q)
+  }"#;
+  let lowered = desugar(program);
+
+  assert!(lowered.contains("f[$function0_return, b, a]:"));
+  assert!(lowered.contains("$funcall1_return[p, q]:"));
+}
+
+#[test]
+fn it_avoids_existing_symbols() {
+  let lowered = desugar("{ let $if0_end := 1 if 1 { } }");
+  assert!(lowered.contains("$if1_end:"));
+}
+
+#[test]
+fn it_lowers_to_the_same_code() {
+  let programs = vec![r#"{
+    let y := 0
+    for { let i := 0 } lt(i, 5) { i := add(i, 1) } {
+      switch i
+      case 3: { y := 1 }
+      default: { if y { y := add(y, i) } }
+    }
+    sstore(0, y)
+  }"#,
+                      r#"{
+    function power(base, exponent) -> result {
+      result := 1
+      for { let i := 0 } lt(i, exponent) { i := add(i, 1) } {
+        let next := mul(result, base)
+        if gt(next, 1000) { leave }
+        result := next
+        if eq(i, 3) { break }
+      }
+    }
+    function swap(a, b) -> (c, d) { c := b d := a }
+    let p, q := swap(power(2, 3), 7)
+    sstore(p, add(power(q, 2), 1))
+    let r := power(3, power(1, 2))
+    pop(r)
+  }"#];
+
+  for program in programs {
+    desugar(program);
+  }
+}
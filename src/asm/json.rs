@@ -0,0 +1,372 @@
+//! JSON export of the AST, shaped after solc's compact Yul AST
+use std::collections::BTreeMap;
+use std::io::Write;
+
+extern crate rustc_serialize;
+use self::rustc_serialize::json::{Json, ToJson};
+use self::rustc_serialize::hex::ToHex;
+
+use asm::ast::*;
+
+pub struct JsonPrinter {}
+
+impl JsonPrinter {
+  pub fn print<W: Write>(block: &Node<Block>, out: &mut W) {
+    write!(out, "{}\n", block.to_json()).ok();
+  }
+}
+
+type Object = BTreeMap<String, Json>;
+
+/// Fields common to every node: its kind, id and `start:length:source`
+/// location as used by solc
+fn object<T>(node_type: &str, node: &Node<T>) -> Object {
+  let mut object = BTreeMap::new();
+  object.insert("nodeType".to_string(), node_type.to_json());
+  object.insert("id".to_string(), node.id.to_json());
+  object.insert("src".to_string(), src(node.span).to_json());
+  object
+}
+
+fn src(span: Span) -> String {
+  format!("{}:{}:0", span.start, span.end - span.start)
+}
+
+fn type_name(type_name: &Option<String>) -> Json {
+  type_name.as_ref().map_or("", |t| t.as_str()).to_json()
+}
+
+/// Declared names are `YulTypedName`s, with an empty type unless annotated
+fn typed_names(identifiers: &[Node<Identifier>]) -> Json {
+  let names = identifiers.iter()
+    .map(|identifier| {
+      let mut name = object("YulTypedName", identifier);
+      name.insert("name".to_string(), identifier.symbol.to_json());
+      name.insert("type".to_string(), type_name(&identifier.type_name));
+      Json::Object(name)
+    })
+    .collect();
+
+  Json::Array(names)
+}
+
+fn literal<T>(node: &Node<T>, kind: &str, value: String) -> Object {
+  let mut literal = object("YulLiteral", node);
+  literal.insert("kind".to_string(), kind.to_json());
+  literal.insert("value".to_string(), value.to_json());
+  literal
+}
+
+impl ToJson for Node<Block> {
+  fn to_json(&self) -> Json {
+    let mut block = object("YulBlock", self);
+    block.insert("statements".to_string(), self.statements.to_json());
+    Json::Object(block)
+  }
+}
+
+impl ToJson for Node<Statement> {
+  fn to_json(&self) -> Json {
+    match **self {
+      Statement::Block(ref node) => node.to_json(),
+      Statement::FunctionDefinition(ref node) => node.to_json(),
+      Statement::VariableDeclaration(ref node) => node.to_json(),
+      Statement::Assignment(ref node) => node.to_json(),
+      Statement::Expression(ref node) => {
+        let mut statement = object("YulExpressionStatement", self);
+        statement.insert("expression".to_string(), node.to_json());
+        Json::Object(statement)
+      }
+      Statement::If(ref node) => node.to_json(),
+      Statement::Switch(ref node) => node.to_json(),
+      Statement::ForLoop(ref node) => node.to_json(),
+      Statement::ControlOp(ref node) => node.to_json(),
+      Statement::SubAssembly(ref node) => node.to_json(),
+      Statement::LabelDefinition(ref node) => node.to_json(),
+      Statement::Instruction(ref node) => node.to_json(),
+      Statement::StackAssignment(ref node) => node.to_json(),
+      Statement::Error(ref node) => node.to_json(),
+    }
+  }
+}
+
+impl ToJson for Node<Expression> {
+  fn to_json(&self) -> Json {
+    match **self {
+      Expression::Identifier(ref node) => node.to_json(),
+      Expression::Literal(ref node) => node.to_json(),
+      Expression::FunctionCall(ref node) => node.to_json(),
+    }
+  }
+}
+
+impl ToJson for Node<FunctionDefinition> {
+  fn to_json(&self) -> Json {
+    let mut definition = object("YulFunctionDefinition", self);
+    definition.insert("name".to_string(), self.identifier.symbol.to_json());
+    definition.insert("parameters".to_string(), typed_names(&self.arguments));
+    definition.insert("returnVariables".to_string(),
+                      typed_names(self.returns.as_ref().map_or(&[], |returns| &returns[..])));
+    definition.insert("body".to_string(), self.body.to_json());
+    Json::Object(definition)
+  }
+}
+
+impl ToJson for Node<VariableDeclaration> {
+  fn to_json(&self) -> Json {
+    let mut declaration = object("YulVariableDeclaration", self);
+    declaration.insert("variables".to_string(), typed_names(&self.identifiers));
+    declaration.insert("value".to_string(), self.expression.to_json());
+    Json::Object(declaration)
+  }
+}
+
+impl ToJson for Node<Assignment> {
+  fn to_json(&self) -> Json {
+    let mut assignment = object("YulAssignment", self);
+    assignment.insert("variableNames".to_string(), self.identifiers.to_json());
+    assignment.insert("value".to_string(), self.expression.to_json());
+    Json::Object(assignment)
+  }
+}
+
+impl ToJson for Node<If> {
+  fn to_json(&self) -> Json {
+    let mut statement = object("YulIf", self);
+    statement.insert("condition".to_string(), self.condition.to_json());
+    statement.insert("body".to_string(), self.body.to_json());
+    Json::Object(statement)
+  }
+}
+
+impl ToJson for Node<Switch> {
+  fn to_json(&self) -> Json {
+    let mut cases: Vec<Json> = self.cases.iter().map(|case| case.to_json()).collect();
+
+    // the default case has no node of its own, so it borrows its body's span
+    // and, having no id to give, leaves `id` out
+    if let Some(ref block) = self.default {
+      let mut default = BTreeMap::new();
+      default.insert("nodeType".to_string(), "YulCase".to_json());
+      default.insert("src".to_string(), src(block.span).to_json());
+      default.insert("value".to_string(), "default".to_json());
+      default.insert("body".to_string(), block.to_json());
+      cases.push(Json::Object(default));
+    }
+
+    let mut switch = object("YulSwitch", self);
+    switch.insert("expression".to_string(), self.expression.to_json());
+    switch.insert("cases".to_string(), Json::Array(cases));
+    Json::Object(switch)
+  }
+}
+
+impl ToJson for Node<Case> {
+  fn to_json(&self) -> Json {
+    let mut case = object("YulCase", self);
+    case.insert("value".to_string(), self.expression.to_json());
+    case.insert("body".to_string(), self.block.to_json());
+    Json::Object(case)
+  }
+}
+
+impl ToJson for Node<ForLoop> {
+  fn to_json(&self) -> Json {
+    let mut for_loop = object("YulForLoop", self);
+    for_loop.insert("pre".to_string(), self.init.to_json());
+    for_loop.insert("condition".to_string(), self.condition.to_json());
+    for_loop.insert("post".to_string(), self.post.to_json());
+    for_loop.insert("body".to_string(), self.body.to_json());
+    Json::Object(for_loop)
+  }
+}
+
+impl ToJson for Node<ControlOp> {
+  fn to_json(&self) -> Json {
+    match **self {
+      ControlOp::Break => Json::Object(object("YulBreak", self)),
+      ControlOp::Continue => Json::Object(object("YulContinue", self)),
+      ControlOp::Leave => Json::Object(object("YulLeave", self)),
+    }
+  }
+}
+
+impl ToJson for Node<SubAssembly> {
+  fn to_json(&self) -> Json {
+    let mut assembly = object("YulSubAssembly", self);
+    assembly.insert("name".to_string(), self.identifier.symbol.to_json());
+    assembly.insert("body".to_string(), self.block.to_json());
+    Json::Object(assembly)
+  }
+}
+
+impl ToJson for Node<LabelDefinition> {
+  fn to_json(&self) -> Json {
+    let mut label = object("YulLabelDefinition", self);
+    label.insert("name".to_string(), self.identifier.symbol.to_json());
+    match self.stack {
+      Some(LabelStack::Height(height)) => {
+        label.insert("stackHeight".to_string(), height.to_json());
+      }
+      Some(LabelStack::Items(ref items)) => {
+        label.insert("stackItems".to_string(), items.to_json());
+      }
+      None => {}
+    }
+    Json::Object(label)
+  }
+}
+
+impl ToJson for Node<Instruction> {
+  fn to_json(&self) -> Json {
+    let mut instruction = object("YulInstruction", self);
+    instruction.insert("instruction".to_string(),
+                       self.opcode.mnemonic().to_lowercase().to_json());
+    Json::Object(instruction)
+  }
+}
+
+impl ToJson for Node<StackAssignment> {
+  fn to_json(&self) -> Json {
+    let mut assignment = object("YulStackAssignment", self);
+    assignment.insert("variableName".to_string(), self.identifier.to_json());
+    Json::Object(assignment)
+  }
+}
+
+impl ToJson for Node<SyntaxError> {
+  fn to_json(&self) -> Json {
+    let mut error = object("YulSyntaxError", self);
+    error.insert("text".to_string(), self.text.to_json());
+    Json::Object(error)
+  }
+}
+
+impl ToJson for Node<FunctionCall> {
+  fn to_json(&self) -> Json {
+    let mut call = object("YulFunctionCall", self);
+    call.insert("functionName".to_string(), self.identifier.to_json());
+    call.insert("arguments".to_string(), self.arguments.to_json());
+    Json::Object(call)
+  }
+}
+
+impl ToJson for Node<Identifier> {
+  fn to_json(&self) -> Json {
+    let mut identifier = object("YulIdentifier", self);
+    identifier.insert("name".to_string(), self.symbol.to_json());
+    Json::Object(identifier)
+  }
+}
+
+impl ToJson for Node<Literal> {
+  fn to_json(&self) -> Json {
+    // the literal's own node, so that `src` includes any type annotation
+    let mut literal = match self.value {
+      LiteralValue::HexNumber(ref node) => literal(self, "number", format!("0x{}", node.digits)),
+      LiteralValue::DecNumber(ref node) => literal(self, "number", node.digits.clone()),
+      LiteralValue::StringLiteral(ref node) => {
+        let value = String::from_utf8_lossy(&node.bytes).into_owned();
+        let mut literal = literal(self, "string", value);
+        literal.insert("hexValue".to_string(), node.bytes.to_hex().to_json());
+        literal
+      }
+      LiteralValue::HexLiteral(ref node) => {
+        let value = String::from_utf8_lossy(&node.bytes).into_owned();
+        let mut literal = literal(self, "string", value);
+        literal.insert("hexValue".to_string(), node.bytes.to_hex().to_json());
+        literal
+      }
+      // only found in trees that failed to parse
+      LiteralValue::Invalid(ref error) => literal(self, "invalid", error.to_string()),
+    };
+    literal.insert("type".to_string(), type_name(&self.type_name));
+
+    Json::Object(literal)
+  }
+}
+
+#[cfg(test)]
+use asm::grammar;
+
+#[cfg(test)]
+fn export(program: &str) -> Json {
+  let mut buf = vec![];
+  JsonPrinter::print(&grammar::block(program).unwrap(), &mut buf);
+  Json::from_str(&String::from_utf8(buf).unwrap()).unwrap()
+}
+
+#[test]
+fn it_exports_nodes_with_kinds_and_locations() {
+  let json = export("{ let x := add(1, 0x20) }");
+
+  assert_eq!(json.find("nodeType").unwrap().as_string(), Some("YulBlock"));
+  assert_eq!(json.find("src").unwrap().as_string(), Some("0:25:0"));
+  assert!(json.find("id").unwrap().is_u64());
+
+  let declaration = &json.find("statements").unwrap().as_array().unwrap()[0];
+  assert_eq!(declaration.find("nodeType").unwrap().as_string(),
+             Some("YulVariableDeclaration"));
+  assert_eq!(declaration.find("src").unwrap().as_string(), Some("2:21:0"));
+
+  let variable = &declaration.find("variables").unwrap().as_array().unwrap()[0];
+  assert_eq!(variable.find("name").unwrap().as_string(), Some("x"));
+
+  let call = declaration.find("value").unwrap();
+  assert_eq!(call.find_path(&["functionName", "name"]).unwrap().as_string(),
+             Some("add"));
+
+  let arguments = call.find("arguments").unwrap().as_array().unwrap();
+  assert_eq!(arguments[0].find("value").unwrap().as_string(), Some("1"));
+  assert_eq!(arguments[1].find("value").unwrap().as_string(), Some("0x20"));
+  assert_eq!(arguments[1].find("kind").unwrap().as_string(), Some("number"));
+}
+
+#[test]
+fn it_exports_switch_defaults_as_cases() {
+  let json = export("{ switch x case \"a\": { } default: { } }");
+
+  let switch = &json.find("statements").unwrap().as_array().unwrap()[0];
+  let cases = switch.find("cases").unwrap().as_array().unwrap();
+  assert_eq!(cases.len(), 2);
+  assert_eq!(cases[0].find_path(&["value", "hexValue"]).unwrap().as_string(),
+             Some("61"));
+  assert_eq!(cases[1].find("value").unwrap().as_string(), Some("default"));
+  assert_eq!(cases[1].find("id"), None);
+}
+
+#[test]
+fn it_exports_label_stacks() {
+  let json = export("{ jump(f) f[r, a]: { } l[2]: }");
+  let statements = json.find("statements").unwrap().as_array().unwrap();
+
+  let items = statements[1].find("stackItems").unwrap().as_array().unwrap();
+  assert_eq!(items[1].find("name").unwrap().as_string(), Some("a"));
+  assert_eq!(statements[3].find("stackHeight").unwrap().as_u64(), Some(2));
+}
+
+#[test]
+fn it_exports_literals_with_their_own_ids_and_locations() {
+  let program = "{ pop(1:u256) }";
+  let block = grammar::block(program).unwrap();
+  let literal_id = match *block.statements[0] {
+    Statement::Expression(ref expression) => {
+      match **expression {
+        Expression::FunctionCall(ref call) => {
+          match *call.arguments[0] {
+            Expression::Literal(ref literal) => literal.id,
+            _ => panic!("expected literal"),
+          }
+        }
+        _ => panic!("expected function call"),
+      }
+    }
+    _ => panic!("expected expression"),
+  };
+
+  let json = export(program);
+  let statement = &json.find("statements").unwrap().as_array().unwrap()[0];
+  let literal = &statement.find_path(&["expression", "arguments"]).unwrap()[0];
+  assert_eq!(literal.find("id").unwrap().as_u64(), Some(literal_id as u64));
+  assert_eq!(literal.find("src").unwrap().as_string(), Some("6:6:0"));
+}
@@ -1,9 +1,72 @@
 peg_file! __grammar("grammar.rustpeg");
 
+/// The generated parser, with each tree it parses numbered from 0 so that
+/// ids don't depend on what was parsed before
 pub mod grammar {
   pub use asm::__grammar::*;
+
+  use asm::__grammar;
+  use asm::ast::*;
+  use asm::ast::ids::{Ids, Numbered};
+  use asm::trivia::Comment;
+
+  macro_rules! numbered_rules {
+    ($($rule:ident -> $t:ty),*) => {
+      $(
+        pub fn $rule(input: &str) -> ParseResult<$t> {
+          __grammar::$rule(input, false).map(|mut node| {
+            node.number(&mut Ids::new());
+            node
+          })
+        }
+      )*
+    }
+  }
+
+  numbered_rules!(block -> Node<Block>,
+                  object -> Node<Object>,
+                  data -> Node<Data>,
+                  statement -> Node<Statement>,
+                  expression -> Node<Expression>,
+                  function_definition -> Node<FunctionDefinition>,
+                  variable_declaration -> Node<VariableDeclaration>,
+                  assignment -> Node<Assignment>,
+                  label_definition -> Node<LabelDefinition>,
+                  instruction -> Node<Instruction>,
+                  stack_assignment -> Node<StackAssignment>,
+                  if_statement -> Node<If>,
+                  switch -> Node<Switch>,
+                  case -> Node<Case>,
+                  default -> Node<Block>,
+                  for_loop -> Node<ForLoop>,
+                  control_op -> Node<ControlOp>,
+                  sub_assembly -> Node<SubAssembly>,
+                  function_call -> Node<FunctionCall>,
+                  identifier -> Node<Identifier>,
+                  literal -> Node<Literal>,
+                  string_literal -> Node<StringLiteral>,
+                  hex_literal -> Node<HexLiteral>,
+                  hex_number -> Node<HexNumber>,
+                  dec_number -> Node<DecNumber>);
+
+  /// Parses a block as `block` does, but leaves statements that don't parse
+  /// in the tree as `Statement::Error` nodes, see `asm::recovery`
+  pub fn recovering_block(input: &str) -> ParseResult<Node<Block>> {
+    __grammar::block(input, true).map(|mut node| {
+      node.number(&mut Ids::new());
+      node
+    })
+  }
+
+  pub fn comments(input: &str) -> ParseResult<Vec<Comment>> {
+    __grammar::comments(input, false)
+  }
 }
 
 pub mod ast;
 pub mod pretty;
+pub mod json;
+pub mod trivia;
+pub mod recovery;
+pub mod desugar;
 pub mod process;
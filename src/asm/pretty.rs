@@ -1,10 +1,16 @@
 use std::io::Write;
+use std::str;
+
+extern crate rustc_serialize;
+use self::rustc_serialize::hex::ToHex;
 
 use asm::ast::*;
+use asm::trivia::Trivia;
 use self::visitor::*;
 
 pub struct PrettyPrinter<'a, W: 'a> {
   out: &'a mut W,
+  trivia: &'a Trivia,
 
   indent: u32,
   statement_newlines: bool,
@@ -13,12 +19,59 @@ pub struct PrettyPrinter<'a, W: 'a> {
 
 impl<'a, W: Write> PrettyPrinter<'a, W> {
   pub fn print(block: &'a Node<Block>, out: &mut W) {
+    PrettyPrinter::print_with_trivia(block, &Trivia::new(), out);
+  }
+
+  /// Prints the block along with the comments collected from its source
+  pub fn print_with_trivia(block: &'a Node<Block>, trivia: &'a Trivia, out: &mut W) {
+    let mut printer = PrettyPrinter::new(trivia, out);
+
+    printer.print_leading(block.id);
+    printer.visit_block(block);
+    printer.print_after_root(block.id);
+  }
+
+  pub fn print_object(object: &'a Node<Object>, out: &mut W) {
+    PrettyPrinter::print_object_with_trivia(object, &Trivia::new(), out);
+  }
+
+  /// Prints the object along with the comments collected from its source
+  pub fn print_object_with_trivia(object: &'a Node<Object>, trivia: &'a Trivia, out: &mut W) {
+    let mut printer = PrettyPrinter::new(trivia, out);
+
+    printer.print_leading(object.id);
+    printer.visit_object(object);
+    printer.print_after_root(object.id);
+  }
+
+  fn new(trivia: &'a Trivia, out: &'a mut W) -> PrettyPrinter<'a, W> {
     PrettyPrinter {
-        indent: 0,
-        statement_newlines: false,
-        out: out,
-      }
-      .visit_block(block);
+      indent: 0,
+      statement_newlines: false,
+      out: out,
+      trivia: trivia,
+    }
+  }
+
+  /// Writes the comments on the lines before a node, leaving the output
+  /// indented for the node itself
+  fn print_leading(&mut self, nid: Nid) {
+    for comment in self.trivia.leading(nid) {
+      write!(&mut self.out, "{}", comment.text).ok();
+      self.newline();
+    }
+  }
+
+  fn print_trailing(&mut self, nid: Nid) {
+    for comment in self.trivia.trailing(nid) {
+      write!(&mut self.out, " {}", comment.text).ok();
+    }
+  }
+
+  fn print_after_root(&mut self, nid: Nid) {
+    for comment in self.trivia.trailing(nid) {
+      write!(&mut self.out, "\n{}", comment.text).ok();
+    }
   }
 
   pub fn newline(&mut self) {
@@ -40,21 +93,104 @@ impl<'a, W: Write> PrettyPrinter<'a, W> {
     write!(&mut self.out, ", ").ok();
   }
 
+  fn print_type(&mut self, type_name: &Option<String>) {
+    if let Some(ref type_name) = *type_name {
+      write!(&mut self.out, ":{}", type_name).ok();
+    }
+  }
+
   fn print_after_list(&mut self) {
     write!(&mut self.out, ")").ok();
   }
 }
 
+/// Escapes string literal contents so they read back as the same bytes.
+/// Bytes that aren't valid UTF-8 are written as `\xNN`
+fn escape(bytes: &[u8]) -> String {
+  let mut escaped = String::new();
+  match str::from_utf8(bytes) {
+    Ok(string) => {
+      for c in string.chars() {
+        escape_char(c, &mut escaped);
+      }
+    }
+    Err(_) => {
+      for &b in bytes {
+        if b < 0x80 {
+          escape_char(b as char, &mut escaped);
+        } else {
+          escaped.push_str(&format!("\\x{:02x}", b));
+        }
+      }
+    }
+  }
+
+  escaped
+}
+
+fn escape_char(c: char, escaped: &mut String) {
+  match c {
+    '"' => escaped.push_str("\\\""),
+    '\\' => escaped.push_str("\\\\"),
+    '\n' => escaped.push_str("\\n"),
+    '\r' => escaped.push_str("\\r"),
+    '\t' => escaped.push_str("\\t"),
+    c if c.is_control() && (c as u32) < 0x80 => {
+      escaped.push_str(&format!("\\x{:02x}", c as u32));
+    }
+    c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+    c => escaped.push(c),
+  }
+}
+
 impl<'v, W: Write> Visitor<'v> for PrettyPrinter<'v, W> {
+  fn visit_object(&mut self, o: &'v Node<Object>) {
+    write!(&mut self.out, "object \"{}\" {{", o.name).ok();
+    self.indent += 1;
+
+    self.newline();
+    self.print_leading(o.code.id);
+    write!(&mut self.out, "code ").ok();
+    self.visit_block(&o.code);
+    self.print_trailing(o.code.id);
+
+    for item in &o.items {
+      self.newline();
+      self.print_leading(item.id);
+      match **item {
+        ObjectItem::Object(ref object) => self.visit_object(object),
+        ObjectItem::Data(ref data) => self.visit_data(data),
+      }
+      self.print_trailing(item.id);
+    }
+
+    for comment in self.trivia.dangling(o.id) {
+      self.newline();
+      write!(&mut self.out, "{}", comment.text).ok();
+    }
+
+    self.indent -= 1;
+    self.newline();
+    write!(&mut self.out, "}}").ok();
+  }
+
+  fn visit_data(&mut self, d: &'v Node<Data>) {
+    write!(&mut self.out, "data \"{}\" ", d.name).ok();
+    self.visit_literal(&d.value);
+  }
+
   fn visit_block(&mut self, b: &'v Node<Block>) {
     let number_of_statements = (*b).statements.len();
 
+    // comments are only kept on lines of their own
+    let commented = self.trivia.has_comments(b);
+
     write!(&mut self.out, "{{").ok();
     match number_of_statements {
-      0 => {
+      0 if !commented => {
         write!(&mut self.out, " ").ok();
       }
-      1 => {
+      1 if !commented => {
         write!(&mut self.out, " ").ok();
         let old_statement_newlines = self.statement_newlines;
         self.statement_newlines = false;
@@ -67,6 +203,10 @@ impl<'v, W: Write> Visitor<'v> for PrettyPrinter<'v, W> {
         self.statement_newlines = true;
         self.indent += 1;
         b.walk(self);
+        for comment in self.trivia.dangling(b.id) {
+          self.newline();
+          write!(&mut self.out, "{}", comment.text).ok();
+        }
         self.indent -= 1;
         self.statement_newlines = old_statement_newlines;
         self.newline();
@@ -79,7 +219,10 @@ impl<'v, W: Write> Visitor<'v> for PrettyPrinter<'v, W> {
     if self.statement_newlines {
       self.newline();
     }
+
+    self.print_leading(s.id);
     &s.walk(self);
+    self.print_trailing(s.id);
   }
 
   fn visit_function_definition(&mut self, f: &'v Node<FunctionDefinition>) {
@@ -216,6 +359,13 @@ impl<'v, W: Write> Visitor<'v> for PrettyPrinter<'v, W> {
     }
   }
 
+  fn visit_if(&mut self, i: &'v Node<If>) {
+    write!(&mut self.out, "if ").ok();
+    self.visit_expression(&i.condition);
+    write!(&mut self.out, " ").ok();
+    self.visit_block(&i.body);
+  }
+
   fn visit_for_loop(&mut self, f: &'v Node<ForLoop>) {
     write!(&mut self.out, "for ").ok();
     match **f {
@@ -239,6 +389,9 @@ impl<'v, W: Write> Visitor<'v> for PrettyPrinter<'v, W> {
       ControlOp::Continue => {
         write!(&mut self.out, "continue").ok();
       }
+      ControlOp::Leave => {
+        write!(&mut self.out, "leave").ok();
+      }
     }
   }
 
@@ -253,6 +406,41 @@ impl<'v, W: Write> Visitor<'v> for PrettyPrinter<'v, W> {
     }
   }
 
+  fn visit_label_definition(&mut self, l: &'v Node<LabelDefinition>) {
+    self.visit_identifier(&l.identifier);
+    match l.stack {
+      Some(LabelStack::Height(height)) => {
+        write!(&mut self.out, "[{}]", height).ok();
+      }
+      Some(LabelStack::Items(ref items)) => {
+        write!(&mut self.out, "[").ok();
+        for (i, identifier) in items.iter().enumerate() {
+          if i != 0 {
+            self.print_between_list_items();
+          }
+
+          self.visit_identifier(identifier);
+        }
+        write!(&mut self.out, "]").ok();
+      }
+      None => {}
+    }
+    write!(&mut self.out, ":").ok();
+  }
+
+  fn visit_instruction(&mut self, i: &'v Node<Instruction>) {
+    write!(&mut self.out, "{}", i.opcode.mnemonic().to_lowercase()).ok();
+  }
+
+  fn visit_stack_assignment(&mut self, a: &'v Node<StackAssignment>) {
+    write!(&mut self.out, "=: ").ok();
+    self.visit_identifier(&a.identifier);
+  }
+
+  fn visit_syntax_error(&mut self, e: &'v Node<SyntaxError>) {
+    write!(&mut self.out, "{}", e.text).ok();
+  }
+
   fn visit_function_call(&mut self, c: &'v Node<FunctionCall>) {
     match **c {
       FunctionCall { ref identifier, ref arguments } => {
@@ -272,22 +460,28 @@ impl<'v, W: Write> Visitor<'v> for PrettyPrinter<'v, W> {
 
   fn visit_identifier(&mut self, i: &'v Node<Identifier>) {
     write!(&mut self.out, "{}", (*i).symbol).ok();
+    self.print_type(&i.type_name);
+  }
+
+  fn visit_literal(&mut self, l: &'v Node<Literal>) {
+    &l.walk(self);
+    self.print_type(&l.type_name);
   }
 
   fn visit_string_literal(&mut self, s: &'v Node<StringLiteral>) {
-    write!(&mut self.out, "\"{}\"", (*s).string).ok();
+    write!(&mut self.out, "\"{}\"", escape(&(*s).bytes)).ok();
   }
 
   fn visit_hex_literal(&mut self, x: &'v Node<HexLiteral>) {
-    write!(&mut self.out, "hex\"{:?}\"", (*x).bytes).ok();
+    write!(&mut self.out, "hex\"{}\"", (*x).bytes.to_hex()).ok();
   }
 
   fn visit_hex_number(&mut self, x: &'v Node<HexNumber>) {
-    write!(&mut self.out, "{:x}", (*x).uint).ok();
+    write!(&mut self.out, "0x{}", (*x).digits).ok();
   }
 
   fn visit_dec_number(&mut self, n: &'v Node<DecNumber>) {
-    write!(&mut self.out, "{}", (*n).uint).ok();
+    write!(&mut self.out, "{}", (*n).digits).ok();
   }
 }
 
@@ -301,15 +495,28 @@ use std::io::BufWriter;
 use std::str::from_utf8;
 
 #[cfg(test)]
-fn assert_print_quine(program: &str) {
-  let block = grammar::block(program).unwrap();
+use asm::ast::structural::Structural;
+
+#[cfg(test)]
+fn print(block: &Node<Block>, source: &str) -> String {
+  let trivia = Trivia::collect(block, source);
   let mut buf = vec![];
   {
     let mut out: BufWriter<_> = BufWriter::new(&mut buf);
-    PrettyPrinter::print(&block, &mut out);
+    PrettyPrinter::print_with_trivia(block, &trivia, &mut out);
   }
 
-  let s: &str = from_utf8(&mut buf).unwrap();
+  from_utf8(&buf).unwrap().to_string()
+}
+
+#[cfg(test)]
+fn assert_print_quine(program: &str) {
+  let block = grammar::block(program).unwrap();
+  let s = print(&block, program);
+
+  let reparsed = grammar::block(&s).expect(&s);
+  assert_eq!(reparsed.structure(), block.structure());
+
   if s != program {
     println!("{}", s);
     panic!("source doesn't line up with output");
@@ -400,3 +607,308 @@ fn it_writes_sub_assemblies() {
 } } }"#;
   assert_print_quine(program);
 }
+
+#[test]
+fn it_writes_ifs() {
+  let program;
+  program = r#"{
+  if lt(x, 1) { x := 1 }
+  if x {
+    y
+    z
+  }
+}"#;
+  assert_print_quine(program);
+}
+
+#[test]
+fn it_writes_labels() {
+  let mut program;
+  program = r#"{
+  loop:
+  jump(loop)
+}"#;
+  assert_print_quine(program);
+
+  program = r#"{
+  jump(f)
+  f[ret, a]:
+  { jump(ret) }
+  after[2]:
+}"#;
+  assert_print_quine(program);
+}
+
+#[test]
+fn it_writes_instructions() {
+  let program;
+  program = r#"{
+  let x := 0
+  1
+  dup1
+  =: x
+  pop
+}"#;
+  assert_print_quine(program);
+}
+
+#[test]
+fn it_writes_comments() {
+  let mut program;
+  program = r#"// header
+{
+  // leading
+  let x := 1 // trailing
+  /* block */
+  function f() {
+    // only a comment
+  }
+  x
+  // dangling
+}"#;
+  assert_print_quine(program);
+
+  program = r#"{ let s := "// not a comment" }"#;
+  assert_print_quine(program);
+}
+
+#[test]
+fn it_writes_escaped_strings() {
+  let program;
+  program = r#"{
+  let s := "\"quoted\" \\ \n\t\x00 é"
+  let t := "\xff"
+}"#;
+  assert_print_quine(program);
+}
+
+#[test]
+fn it_writes_literals_as_written() {
+  let program;
+  program = r#"{
+  let x := 0x00Ff
+  let y := 007
+  let z := hex"ff11"
+  let s := "fnord"
+}"#;
+  assert_print_quine(program);
+}
+
+#[test]
+fn it_writes_control_ops() {
+  let program = r#"{ function f() {
+  for { } 1 { } {
+    if 1 { break }
+    continue
+  }
+  leave
+} }"#;
+  assert_print_quine(program);
+}
+
+#[test]
+fn it_writes_types() {
+  let program = r#"{
+  function f(a:u32, b) -> (r:bool) { r := lt(a, b) }
+  let (x:u256, y) := f(1:u32, "abc":bytes32)
+}"#;
+  assert_print_quine(program);
+}
+
+#[test]
+fn it_writes_objects() {
+  let program = r#"// contract
+object "Token" {
+  code {
+    let size := datasize("Runtime")
+    return(0, size)
+  }
+  object "Runtime" {
+    // deployed
+    code { stop() }
+    data "Table" hex"00ff" // lookup
+  }
+  data "Name" "token"
+  // end
+}"#;
+
+  let object = grammar::object(program).unwrap();
+  let trivia = Trivia::collect_object(&object, program);
+  let mut buf = vec![];
+  {
+    let mut out: BufWriter<_> = BufWriter::new(&mut buf);
+    PrettyPrinter::print_object_with_trivia(&object, &trivia, &mut out);
+  }
+
+  let printed = from_utf8(&buf).unwrap();
+  assert_eq!(grammar::object(printed).unwrap().structure(), object.structure());
+  assert_eq!(printed, program);
+}
+
+// Round-tripping
+//
+// Generates random programs covering the whole grammar, with comments and
+// odd spacing between tokens, and checks that printing them loses nothing
+#[cfg(test)]
+struct Generator {
+  seed: u64,
+  depth: usize,
+}
+
+#[cfg(test)]
+const MAX_DEPTH: usize = 3;
+
+#[cfg(test)]
+impl Generator {
+  fn random(&mut self, n: usize) -> usize {
+    // xorshift
+    self.seed ^= self.seed << 13;
+    self.seed ^= self.seed >> 7;
+    self.seed ^= self.seed << 17;
+    (self.seed % n as u64) as usize
+  }
+
+  fn pick(&mut self, choices: &[&str]) -> String {
+    let i = self.random(choices.len());
+    choices[i].to_string()
+  }
+
+  fn list<F: FnMut(&mut Generator) -> String>(&mut self, max: usize, mut f: F) -> Vec<String> {
+    let n = self.random(max + 1);
+    (0..n).map(|_| f(self)).collect()
+  }
+
+  fn space(&mut self) -> String {
+    self.pick(&[" ", "\n  ", " /* note */ ", " // note\n"])
+  }
+
+  fn identifier(&mut self) -> String {
+    self.pick(&["a", "b", "x1", "foo", "bar_2", "$tmp", "_z"])
+  }
+
+  fn typed_identifier(&mut self) -> String {
+    self.identifier() + &self.type_annotation()
+  }
+
+  fn type_annotation(&mut self) -> String {
+    self.pick(&["", "", ":u256", ":bool", ":s8"])
+  }
+
+  fn block(&mut self) -> String {
+    self.depth += 1;
+    let max = if self.depth > MAX_DEPTH { 0 } else { 4 };
+    let statements = self.list(max, |g| g.statement() + &g.space());
+    self.depth -= 1;
+
+    let space = self.space();
+    format!("{{{}{}}}", space, statements.concat())
+  }
+
+  fn statement(&mut self) -> String {
+    let kinds = if self.depth > MAX_DEPTH { 8 } else { 13 };
+    match self.random(kinds) {
+      0 => {
+        let identifiers = self.list(2, |g| g.typed_identifier());
+        let expression = self.expression();
+        match identifiers.len() {
+          0 | 1 => format!("let {} := {}", self.typed_identifier(), expression),
+          _ => format!("let ({}) := {}", identifiers.join(", "), expression),
+        }
+      }
+      1 => format!("{} := {}", self.identifier(), self.expression()),
+      2 => self.expression(),
+      3 => self.pick(&["break", "continue", "leave"]),
+      4 => format!("{}:", self.identifier()),
+      5 => self.pick(&["pop", "dup1", "swap1", "mload", "jumpdest", "stop"]),
+      6 => format!("=: {}", self.identifier()),
+      7 => self.block(),
+      8 => {
+        let name = self.identifier();
+        let arguments = self.list(3, |g| g.typed_identifier());
+        let returns = match self.list(2, |g| g.typed_identifier()) {
+          ref returns if returns.is_empty() => String::new(),
+          returns => format!(" -> ({})", returns.join(", ")),
+        };
+        format!("function {}({}){} {}", name, arguments.join(", "), returns, self.block())
+      }
+      9 => {
+        let expression = self.expression();
+        let cases = self.list(3, |g| format!(" case {}: {}", g.literal(), g.block()));
+        let default = match self.random(2) {
+          0 => String::new(),
+          _ => format!(" default: {}", self.block()),
+        };
+        format!("switch {}{}{}", expression, cases.concat(), default)
+      }
+      10 => {
+        format!("for {} {} {} {}",
+                self.block(),
+                self.expression(),
+                self.block(),
+                self.block())
+      }
+      11 => format!("assembly {} {}", self.identifier(), self.block()),
+      _ => format!("if {} {}", self.expression(), self.block()),
+    }
+  }
+
+  fn expression(&mut self) -> String {
+    match self.random(3) {
+      0 => self.identifier(),
+      1 => self.literal(),
+      _ if self.depth > MAX_DEPTH => self.literal(),
+      _ => {
+        let name = self.pick(&["add", "mstore", "foo", "$tmp"]);
+        self.depth += 1;
+        let arguments = self.list(3, |g| g.expression());
+        self.depth -= 1;
+
+        let mut call = format!("{}(", name);
+        for (i, argument) in arguments.iter().enumerate() {
+          if i != 0 {
+            call.push_str(",");
+            call.push_str(&self.space());
+          }
+          call.push_str(argument);
+        }
+        call + ")"
+      }
+    }
+  }
+
+  fn literal(&mut self) -> String {
+    let value = match self.random(4) {
+      0 => self.pick(&["0", "1", "007", "115792089237316195423570985008687907853269984665640564039457584007913129639935"]),
+      1 => self.pick(&["0x0", "0x00ff", "0xB3de648b", "0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"]),
+      2 => {
+        self.pick(&["\"\"",
+                    "\"fnord\"",
+                    "\"// not /* a comment\"",
+                    "'it\\'s \"quoted\"'",
+                    "\"\\x00\\xff\\u00e9\\t\\\\\""])
+      }
+      _ => self.pick(&["hex\"\"", "hex\"aBff\"", "hex'0011'"]),
+    };
+    value + &self.type_annotation()
+  }
+}
+
+#[test]
+fn it_round_trips_generated_programs() {
+  let mut generator = Generator { seed: 0x5eed, depth: 0 };
+
+  for _ in 0..500 {
+    let program = generator.block();
+    let block = grammar::block(&program).expect(&program);
+
+    let printed = print(&block, &program);
+    let reparsed = grammar::block(&printed).expect(&printed);
+
+    assert_eq!(reparsed.structure(),
+               block.structure(),
+               "{}\n\nprinted as\n\n{}",
+               program,
+               printed);
+    assert_eq!(print(&reparsed, &printed), printed);
+  }
+}
@@ -1,10 +1,16 @@
-use std::io::{self, BufReader, Write, Read};
+use std::collections::HashSet;
+use std::io::{self, BufReader, BufWriter, Write, Read};
 use std::fs::File;
 use process::{Processor, ProcessResult};
 use process::state::{ProcessState, ErrorState, HasConfig, HasAST, HasSource};
-use config::{Config, Source};
+use process::diagnostic::Diagnostic;
+use config::{Config, Source, Target};
 use asm;
-use asm::ast::{Node, Block};
+use asm::recovery;
+use asm::desugar::Desugarer;
+use asm::pretty::PrettyPrinter;
+use asm::ast::{Node, Block, Span, LiteralError};
+use asm::ast::visitor::Visitor;
 
 
 // Sourced
@@ -42,13 +48,15 @@ impl HasSource for Sourced {
 #[derive(Debug, Clone)]
 pub struct Parsed {
   config: Config,
+  source: String,
   ast: Node<Block>,
 }
 
 impl Parsed {
-  pub fn new(ast: Node<Block>, config: Config) -> Parsed {
+  pub fn new(ast: Node<Block>, source: String, config: Config) -> Parsed {
     Parsed {
       config: config,
+      source: source,
       ast: ast,
     }
   }
@@ -62,6 +70,12 @@ impl HasConfig for Parsed {
   }
 }
 
+impl HasSource for Parsed {
+  fn get_source(self) -> String {
+    self.source
+  }
+}
+
 impl HasAST for Parsed {
   fn get_ast(self) -> Node<Block> {
     self.ast
@@ -69,16 +83,114 @@ impl HasAST for Parsed {
 }
 
 
+// Desugared
+//
+/// The program along with its lowering into labels and jumps
+#[derive(Debug, Clone)]
+pub struct Desugared {
+  config: Config,
+  source: String,
+  ast: Node<Block>,
+  desugared: Node<Block>,
+}
+
+impl Desugared {
+  pub fn new(desugared: Node<Block>, ast: Node<Block>, source: String, config: Config) -> Desugared {
+    Desugared {
+      config: config,
+      source: source,
+      ast: ast,
+      desugared: desugared,
+    }
+  }
+}
+
+impl ProcessState for Desugared {}
+
+impl HasConfig for Desugared {
+  fn get_config(self) -> Config {
+    self.config
+  }
+}
+
+impl HasSource for Desugared {
+  fn get_source(self) -> String {
+    self.source
+  }
+}
+
+impl HasAST for Desugared {
+  fn get_ast(self) -> Node<Block> {
+    self.ast
+  }
+}
+
+
 // ParseError
 //
 #[derive(Debug, Clone)]
 pub struct ParseError {
-  error: asm::grammar::ParseError,
+  errors: Vec<asm::grammar::ParseError>,
+  /// Literals that parse but can't be represented
+  literals: Vec<Node<LiteralError>>,
+  source: String,
+  config: Config,
 }
 
 impl ParseError {
-  pub fn new(error: asm::grammar::ParseError) -> ParseError {
-    ParseError { error: error }
+  pub fn new(errors: Vec<asm::grammar::ParseError>,
+             literals: Vec<Node<LiteralError>>,
+             source: String,
+             config: Config)
+             -> ParseError {
+    ParseError {
+      errors: errors,
+      literals: literals,
+      source: source,
+      config: config,
+    }
+  }
+
+  /// Syntax and literal errors, in the order they appear in the source
+  pub fn diagnostics(&self) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = self.errors
+      .iter()
+      .map(ParseError::diagnostic)
+      .chain(self.literals
+               .iter()
+               .map(|literal| Diagnostic::new("ParseError", literal.to_string(), literal.span)))
+      .collect();
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    diagnostics
+  }
+
+  fn diagnostic(error: &asm::grammar::ParseError) -> Diagnostic {
+    let span = Span::new(error.offset, error.offset);
+    let expected = ParseError::describe_expected(&error.expected);
+    let message = match expected.len() {
+      0 => String::from("expected end of input"),
+      1 => format!("expected {}", expected[0]),
+      _ => format!("expected one of {}", expected.join(", ")),
+    };
+
+    Diagnostic::new("ParseError", message, span).expected(expected)
+  }
+
+  /// Literal tokens are quoted, e.g. `":="`, whereas names given to rules in
+  /// the grammar (such as `identifier`) are left as they are
+  fn describe_expected(expected: &HashSet<&'static str>) -> Vec<String> {
+    let mut descriptions: Vec<String> = expected.iter()
+      .map(|e| match *e {
+        "identifier" | "number" | "hex digit" | "escape sequence" | "object name" |
+        "type name" => e.to_string(),
+        "<character>" => String::from("any character"),
+        _ => format!("{:?}", e),
+      })
+      .collect();
+
+    descriptions.sort();
+    descriptions
   }
 }
 
@@ -86,19 +198,66 @@ impl ProcessState for ParseError {}
 
 impl ErrorState for ParseError {
   fn write<W: Write>(self, out: &mut W) {
-    write!(out, "ParseError: {}\n", self.error).ok();
+    for diagnostic in self.diagnostics() {
+      diagnostic.write(out, &self.source, &self.config);
+    }
   }
 }
 
 
+/// Gathers the literals that parsed but can't be represented
+struct LiteralCollector {
+  literals: Vec<Node<LiteralError>>,
+}
+
+impl LiteralCollector {
+  fn collect(block: &Node<Block>) -> Vec<Node<LiteralError>> {
+    let mut collector = LiteralCollector { literals: vec![] };
+    collector.visit_block(block);
+    collector.literals
+  }
+}
+
+impl<'v> Visitor<'v> for LiteralCollector {
+  fn visit_invalid_literal(&mut self, e: &'v Node<LiteralError>) {
+    self.literals.push(e.clone());
+  }
+}
+
+
+impl<S: HasSource> Processor<S> {
+  pub fn source<'a>(self) -> String {
+    self.state.get_source()
+  }
+}
+
 impl<S: HasConfig> Processor<S> {
   pub fn parse<'a>(self) -> ProcessResult<Parsed, ParseError> {
     let config = self.clone().config();
     let buffer = self.read(config.clone());
     let result = asm::grammar::block(buffer.as_str());
 
-    result.and_then(|ast| Ok(Processor { state: Parsed::new(ast, config) }))
-      .or_else(|err| Err(Processor { state: ParseError::new(err) }))
+    match result {
+      Ok(ast) => {
+        let literals = LiteralCollector::collect(&ast);
+        if literals.is_empty() {
+          Ok(Processor { state: Parsed::new(ast, buffer, config) })
+        } else {
+          Err(Processor { state: ParseError::new(vec![], literals, buffer, config) })
+        }
+      }
+      Err(err) => {
+        // carry on past the first error to report any others along with it
+        let recovered = recovery::parse(buffer.as_str());
+        let mut errors = recovered.errors;
+        if errors.is_empty() {
+          errors.push(err);
+        }
+
+        let literals = LiteralCollector::collect(&recovered.block);
+        Err(Processor { state: ParseError::new(errors, literals, buffer, config) })
+      }
+    }
   }
 
   pub fn config<'a>(self) -> Config {
@@ -123,3 +282,99 @@ impl<S: HasConfig> Processor<S> {
     }
   }
 }
+
+impl<S: HasAST> Processor<S> {
+  pub fn desugar<'a, E: ErrorState>(self) -> ProcessResult<Desugared, E> {
+    let config = self.clone().config();
+    let source = self.clone().source();
+    let ast = self.ast();
+    let desugared = Desugarer::desugar(&ast);
+
+    Ok(Processor { state: Desugared::new(desugared, ast, source, config) })
+  }
+}
+
+impl Processor<Desugared> {
+  pub fn emit_desugared<'a, E: ErrorState>(self) -> ProcessResult<Desugared, E> {
+    let config = self.clone().config();
+
+    if config.targets(Target::Desugared) {
+      let desugared = self.clone().desugared();
+      let mut out: BufWriter<_> = BufWriter::new(io::stdout());
+      PrettyPrinter::print(&desugared, &mut out);
+    }
+
+    Ok(self)
+  }
+
+  pub fn desugared(self) -> Node<Block> {
+    self.state.desugared
+  }
+}
+
+#[test]
+fn it_describes_expected_tokens() {
+  let mut config = Config::new();
+  config.source_str("{ let x := add(1 }");
+
+  let processor = Processor::new().configure(config).ok().unwrap();
+  let error = processor.parse().err().unwrap();
+  let diagnostic = error.state.diagnostics().remove(0);
+
+  assert_eq!(diagnostic.message, "expected one of \")\", \",\"");
+  assert_eq!(diagnostic.span, Span::new(17, 17));
+}
+
+#[test]
+fn it_reports_oversized_literals() {
+  let programs = vec![
+    ("{ let x := 115792089237316195423570985008687907853269984665640564039457584007913129639936 }",
+     "number literal exceeds 256 bits"),
+    ("{ let x := 0x10000000000000000000000000000000000000000000000000000000000000000 }",
+     "number literal exceeds 256 bits"),
+    ("{ let x := \"a string that is longer than 32 bytes\" }",
+     "string literal exceeds 32 bytes"),
+  ];
+
+  for (program, message) in programs {
+    let mut config = Config::new();
+    config.source_str(program);
+
+    let processor = Processor::new().configure(config).ok().unwrap();
+    let error = processor.parse().err().unwrap();
+    let diagnostic = error.state.diagnostics().remove(0);
+
+    assert_eq!(diagnostic.message, message);
+    assert_eq!(diagnostic.span, Span::new(11, program.len() - 2));
+  }
+}
+
+#[test]
+fn it_reports_oversized_literals_along_with_syntax_errors() {
+  let mut config = Config::new();
+  config.source_str("{
+  let x := add(1
+  let y := \"a string that is longer than 32 bytes\"
+}");
+
+  let processor = Processor::new().configure(config).ok().unwrap();
+  let error = processor.parse().err().unwrap();
+  let messages: Vec<String> = error.state.diagnostics().into_iter().map(|d| d.message).collect();
+
+  assert_eq!(messages,
+             vec!["expected one of \")\", \",\"".to_string(),
+                  "string literal exceeds 32 bytes".to_string()]);
+}
+
+#[test]
+fn it_reports_every_syntax_error() {
+  let mut config = Config::new();
+  config.source_str("{\n  let x := add(1\n  x := )\n}");
+
+  let processor = Processor::new().configure(config).ok().unwrap();
+  let error = processor.parse().err().unwrap();
+  let messages: Vec<String> = error.state.diagnostics().into_iter().map(|d| d.message).collect();
+
+  assert_eq!(messages.len(), 2);
+  assert_eq!(messages[0], "expected one of \")\", \",\"");
+}
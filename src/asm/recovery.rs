@@ -0,0 +1,179 @@
+//! Parsing that carries on past syntax errors, so that all of them can be
+//! reported at once
+use std::iter;
+
+use asm::ast::*;
+use asm::ast::visitor::Visitor;
+use asm::grammar::{self, ParseError};
+
+#[derive(Debug, Clone)]
+pub struct Recovered {
+  pub block: Node<Block>,
+  pub errors: Vec<ParseError>,
+}
+
+/// Parses `source`, skipping past anything that doesn't parse up to the end
+/// of its line or the enclosing block, and closing any blocks left open at
+/// the end of the input. Skipped statements are left in the tree as
+/// `Statement::Error` nodes, and each is reported with the error the strict
+/// parser gives for it
+pub fn parse(source: &str) -> Recovered {
+  match grammar::recovering_block(source) {
+    Ok(block) => {
+      let mut collector = ErrorCollector {
+        source: source,
+        errors: vec![],
+      };
+      collector.visit_block(&block);
+
+      Recovered {
+        block: block,
+        errors: collector.errors,
+      }
+    }
+
+    // input that isn't a block at all leaves nothing to resynchronise on
+    Err(error) => {
+      Recovered {
+        block: Block::new(vec![]),
+        errors: vec![error],
+      }
+    }
+  }
+}
+
+struct ErrorCollector<'a> {
+  source: &'a str,
+  errors: Vec<ParseError>,
+}
+
+impl<'a> ErrorCollector<'a> {
+  /// Finds the error the strict parser gives for a skipped span by parsing
+  /// a statement from where it starts, and from the start of the statement
+  /// before it, which may have stopped short of its end. The furthest the
+  /// parses get is where the error is
+  fn note(&mut self, error: &Node<SyntaxError>, previous: Option<usize>) {
+    let mut failures: Vec<ParseError> = iter::once(error.span.start)
+      .chain(previous)
+      .filter_map(|start| {
+        grammar::statement(&self.source[start..]).err().map(|failure| {
+          ParseError { offset: start + failure.offset, ..failure }
+        })
+      })
+      .collect();
+
+    // a block left open at the end of the input could be closed there
+    if error.text.is_empty() {
+      failures.push(ParseError {
+        line: 0,
+        column: 0,
+        offset: error.span.start,
+        expected: iter::once("}").collect(),
+      });
+    }
+
+    let offset = match failures.iter().map(|failure| failure.offset).max() {
+      Some(offset) => offset,
+      None => return,
+    };
+
+    // blocks left open at the end of the input all give up at the same place
+    if self.errors.iter().any(|error| error.offset == offset) {
+      return;
+    }
+
+    let position = Position::new(self.source, offset);
+    self.errors.push(ParseError {
+      line: position.line,
+      column: position.column,
+      offset: offset,
+      expected: failures.into_iter()
+        .filter(|failure| failure.offset == offset)
+        .flat_map(|failure| failure.expected)
+        .collect(),
+    });
+  }
+}
+
+impl<'a, 'v> Visitor<'v> for ErrorCollector<'a> {
+  fn visit_block(&mut self, b: &'v Node<Block>) {
+    let mut previous = None;
+    for statement in &b.statements {
+      match **statement {
+        Statement::Error(ref error) => {
+          self.note(error, previous);
+          previous = None;
+        }
+        _ => {
+          self.visit_statement(statement);
+          previous = Some(statement.span.start);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+fn statements(block: &Node<Block>) -> Vec<&Statement> {
+  block.statements.iter().map(|s| &**s).collect()
+}
+
+#[test]
+fn it_parses_strictly_by_default() {
+  assert!(grammar::block("{ let x := add(1 }").is_err());
+  assert!(grammar::block("{ let x := 1").is_err());
+}
+
+#[test]
+fn it_reports_every_syntax_error() {
+  let recovered = parse("{\n  let x := add(1\n  let y := 2\n  function f(a b) { }\n  y\n}");
+
+  let offsets: Vec<usize> = recovered.errors.iter().map(|e| e.offset).collect();
+  assert_eq!(offsets, vec![21, 47]);
+
+  // `let x := add` parses on its own, leaving the arguments behind
+  let statements = statements(&recovered.block);
+  assert_eq!(statements.len(), 5);
+  match *statements[1] {
+    Statement::Error(ref error) => assert_eq!(error.text, "(1"),
+    ref statement => panic!("expected an error node, found {:?}", statement),
+  }
+  match *statements[3] {
+    Statement::Error(ref error) => assert_eq!(error.text, "function f(a b) { }"),
+    ref statement => panic!("expected an error node, found {:?}", statement),
+  }
+}
+
+#[test]
+fn it_recovers_within_nested_blocks() {
+  let recovered = parse("{ function f() { 1 + 2 } f() }");
+  assert_eq!(recovered.errors.len(), 1);
+
+  let statements = statements(&recovered.block);
+  assert_eq!(statements.len(), 2);
+  match *statements[0] {
+    Statement::FunctionDefinition(ref definition) => {
+      assert_eq!(definition.body.statements.len(), 2);
+    }
+    ref statement => panic!("expected a function definition, found {:?}", statement),
+  }
+}
+
+#[test]
+fn it_closes_blocks_left_open() {
+  let source = "{\n  let x := 1\n  function f() {\n    x := 2\n";
+  let recovered = parse(source);
+
+  let offsets: Vec<usize> = recovered.errors.iter().map(|e| e.offset).collect();
+  assert_eq!(offsets, vec![source.len()]);
+  assert_eq!(recovered.errors[0], grammar::block(source).err().unwrap());
+
+  let statements = statements(&recovered.block);
+  assert_eq!(statements.len(), 3);
+  match *statements[1] {
+    Statement::FunctionDefinition(ref definition) => {
+      assert_eq!(definition.body.statements.len(), 2);
+    }
+    ref statement => panic!("expected a function definition, found {:?}", statement),
+  }
+}
@@ -0,0 +1,228 @@
+//! Comments, which the grammar otherwise skips along with whitespace,
+//! attached to the statements around them so they can be printed back out
+use std::collections::HashMap;
+
+use asm::ast::*;
+use asm::ast::visitor::{Visitor, Walkable};
+use asm::grammar;
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Comment {
+  pub text: String,
+  pub span: Span,
+}
+
+impl Comment {
+  pub fn new(text: &str, span: Span) -> Comment {
+    Comment {
+      text: text.to_string(),
+      span: span,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Trivia {
+  /// Comments on the lines before a statement or object section, or before
+  /// the root
+  leading: HashMap<Nid, Vec<Comment>>,
+  /// Comments following a statement or object section on the same line, or
+  /// after the root
+  trailing: HashMap<Nid, Vec<Comment>>,
+  /// Comments after the last statement of a block or section of an object
+  dangling: HashMap<Nid, Vec<Comment>>,
+}
+
+impl Trivia {
+  pub fn new() -> Trivia {
+    Trivia { ..Default::default() }
+  }
+
+  /// Finds the comments in `source` and attaches each one to a statement of
+  /// the innermost block containing it. Comments in between the parts of a
+  /// statement, e.g. `let x := /* one */ 1`, are moved before it
+  pub fn collect(root: &Node<Block>, source: &str) -> Trivia {
+    let mut collector = ContainerCollector { containers: vec![] };
+    collector.visit_block(root);
+
+    Trivia::collect_in(root.id, root.span, &collector.containers, source)
+  }
+
+  /// As `collect`, for an object. Comments between the sections of an
+  /// object are attached to the sections themselves
+  pub fn collect_object(root: &Node<Object>, source: &str) -> Trivia {
+    let mut collector = ContainerCollector { containers: vec![] };
+    collector.visit_object(root);
+
+    Trivia::collect_in(root.id, root.span, &collector.containers, source)
+  }
+
+  fn collect_in(root: Nid, span: Span, containers: &[Container], source: &str) -> Trivia {
+    let mut trivia = Trivia::new();
+    for comment in grammar::comments(source).unwrap_or(vec![]) {
+      trivia.attach(comment, root, span, containers, source);
+    }
+
+    trivia
+  }
+
+  pub fn leading(&self, nid: Nid) -> &[Comment] {
+    Trivia::comments(&self.leading, nid)
+  }
+
+  pub fn trailing(&self, nid: Nid) -> &[Comment] {
+    Trivia::comments(&self.trailing, nid)
+  }
+
+  pub fn dangling(&self, nid: Nid) -> &[Comment] {
+    Trivia::comments(&self.dangling, nid)
+  }
+
+  /// Whether any comments are attached to the block or its statements
+  pub fn has_comments(&self, block: &Node<Block>) -> bool {
+    !self.dangling(block.id).is_empty() ||
+    block.statements
+      .iter()
+      .any(|s| !self.leading(s.id).is_empty() || !self.trailing(s.id).is_empty())
+  }
+
+  fn comments(map: &HashMap<Nid, Vec<Comment>>, nid: Nid) -> &[Comment] {
+    match map.get(&nid) {
+      Some(comments) => comments,
+      None => &[],
+    }
+  }
+
+  fn attach(&mut self,
+            comment: Comment,
+            root: Nid,
+            span: Span,
+            containers: &[Container],
+            source: &str) {
+    let enclosing = containers.iter()
+      .filter(|c| c.span.start < comment.span.start && comment.span.end <= c.span.end)
+      .min_by_key(|c| c.span.end - c.span.start);
+
+    let container = match enclosing {
+      Some(container) => container,
+      None if comment.span.end <= span.start => {
+        self.leading.entry(root).or_insert(vec![]).push(comment);
+        return;
+      }
+      None => {
+        self.trailing.entry(root).or_insert(vec![]).push(comment);
+        return;
+      }
+    };
+
+    let mut previous: Option<&(Nid, Span)> = None;
+    for child in &container.children {
+      let &(id, child_span) = child;
+      if comment.span.start < child_span.end {
+        match previous {
+          Some(&(previous, previous_span)) if comment.span.end <= child_span.start &&
+                            Trivia::same_line(source, previous_span.end, comment.span.start) => {
+            self.trailing.entry(previous).or_insert(vec![]).push(comment);
+          }
+          _ => self.leading.entry(id).or_insert(vec![]).push(comment),
+        }
+        return;
+      }
+
+      previous = Some(child);
+    }
+
+    match previous {
+      Some(&(previous, previous_span)) if Trivia::same_line(source,
+                                                            previous_span.end,
+                                                            comment.span.start) => {
+        self.trailing.entry(previous).or_insert(vec![]).push(comment);
+      }
+      _ => self.dangling.entry(container.id).or_insert(vec![]).push(comment),
+    }
+  }
+
+  fn same_line(source: &str, start: usize, end: usize) -> bool {
+    !source[start..end].contains('\n')
+  }
+}
+
+/// A block or object, and the statements or sections comments inside it can
+/// be attached to
+struct Container {
+  id: Nid,
+  span: Span,
+  children: Vec<(Nid, Span)>,
+}
+
+struct ContainerCollector {
+  containers: Vec<Container>,
+}
+
+impl<'v> Visitor<'v> for ContainerCollector {
+  fn visit_object(&mut self, o: &'v Node<Object>) {
+    let mut children = vec![(o.code.id, o.code.span)];
+    children.extend(o.items.iter().map(|i| (i.id, i.span)));
+
+    self.containers.push(Container {
+                           id: o.id,
+                           span: o.span,
+                           children: children,
+                         });
+    o.walk(self);
+  }
+
+  fn visit_block(&mut self, b: &'v Node<Block>) {
+    self.containers.push(Container {
+                           id: b.id,
+                           span: b.span,
+                           children: b.statements.iter().map(|s| (s.id, s.span)).collect(),
+                         });
+    b.walk(self);
+  }
+}
+
+#[cfg(test)]
+fn collect(program: &str) -> (Node<Block>, Trivia) {
+  let block = grammar::block(program).unwrap();
+  let trivia = Trivia::collect(&block, program);
+  (block, trivia)
+}
+
+#[test]
+fn it_skips_comment_markers_in_strings() {
+  let comments = grammar::comments("{ let s := \"// not /* a comment\" } // comment").unwrap();
+  assert_eq!(comments, vec![Comment::new("// comment", Span::new(35, 45))]);
+}
+
+#[test]
+fn it_attaches_comments_to_neighbouring_statements() {
+  let (block, trivia) = collect("// header\n{\n  // leading\n  x // trailing\n  y\n  // dangling\n}");
+  let x = &block.statements[0];
+  let y = &block.statements[1];
+
+  assert_eq!(trivia.leading(block.id)[0].text, "// header");
+  assert_eq!(trivia.leading(x.id)[0].text, "// leading");
+  assert_eq!(trivia.trailing(x.id)[0].text, "// trailing");
+  assert!(trivia.leading(y.id).is_empty());
+  assert_eq!(trivia.dangling(block.id)[0].text, "// dangling");
+}
+
+#[test]
+fn it_moves_comments_within_statements_before_them() {
+  let (block, trivia) = collect("{ let x := /* one */ 1 }");
+  let statement = &block.statements[0];
+
+  assert_eq!(trivia.leading(statement.id)[0].text, "/* one */");
+}
+
+#[test]
+fn it_attaches_comments_to_object_sections() {
+  let program = "object \"A\" {\n  // constructor\n  code { }\n  data \"x\" hex\"00\" // x\n  // end\n}";
+  let object = grammar::object(program).unwrap();
+  let trivia = Trivia::collect_object(&object, program);
+
+  assert_eq!(trivia.leading(object.code.id)[0].text, "// constructor");
+  assert_eq!(trivia.trailing(object.items[0].id)[0].text, "// x");
+  assert_eq!(trivia.dangling(object.id)[0].text, "// end");
+}
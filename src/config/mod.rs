@@ -5,6 +5,8 @@ mod process;
 pub struct Config {
   pub source: Source,
   pub targets: HashSet<Target>,
+  pub bytecode_format: BytecodeFormat,
+  pub error_format: ErrorFormat,
 }
 
 impl Config {
@@ -32,6 +34,16 @@ impl Config {
     self
   }
 
+  pub fn bytecode_format<'a>(&'a mut self, format: BytecodeFormat) -> &'a mut Config {
+    self.bytecode_format = format;
+    self
+  }
+
+  pub fn error_format<'a>(&'a mut self, format: ErrorFormat) -> &'a mut Config {
+    self.error_format = format;
+    self
+  }
+
   pub fn targets(self, target: Target) -> bool {
     self.targets.contains(&target)
   }
@@ -44,6 +56,17 @@ pub enum Source {
   Literal { source: String },
 }
 
+impl Source {
+  /// Name to refer to the source by in error messages
+  pub fn name(&self) -> String {
+    match *self {
+      Source::Input => String::from("<stdin>"),
+      Source::File { ref filename } => filename.clone(),
+      Source::Literal { .. } => String::from("<literal>"),
+    }
+  }
+}
+
 impl Default for Source {
   fn default() -> Self {
     Source::Input
@@ -53,4 +76,34 @@ impl Default for Source {
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Target {
   Assembly,
+  AstJson,
+  Bytecode,
+  Desugared,
+  Disassembly,
+  Opcodes,
+  SourceMap,
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum BytecodeFormat {
+  Hex,
+  Raw,
+}
+
+impl Default for BytecodeFormat {
+  fn default() -> Self {
+    BytecodeFormat::Hex
+  }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ErrorFormat {
+  Human,
+  Json,
+}
+
+impl Default for ErrorFormat {
+  fn default() -> Self {
+    ErrorFormat::Human
+  }
 }
@@ -0,0 +1,291 @@
+//! Linear instruction stream produced by code generation
+use std::fmt;
+use std::mem;
+
+extern crate bigint;
+use self::bigint::{U256, Uint};
+
+use asm::ast::Span;
+use evm::opcode::Opcode;
+
+pub type Label = usize;
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum Item {
+  Op(Opcode),
+  Push(U256),
+  PushLabel(Label),
+  Label(Label),
+  /// JUMP into or out of a function
+  Jump(JumpType),
+}
+
+/// Whether a jump calls or returns from a function, for debuggers to keep
+/// track of the call stack
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum JumpType {
+  Regular,
+  IntoFunction,
+  OutOfFunction,
+}
+
+/// A misplaced label, by its name and where it is pushed or placed
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum AssemblyError {
+  UnresolvedLabel(String, Option<Span>),
+  DuplicateLabel(String, Option<Span>),
+}
+
+impl AssemblyError {
+  pub fn span(&self) -> Option<Span> {
+    match *self {
+      AssemblyError::UnresolvedLabel(_, span) |
+      AssemblyError::DuplicateLabel(_, span) => span,
+    }
+  }
+}
+
+impl fmt::Display for AssemblyError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      AssemblyError::UnresolvedLabel(ref name, _) => write!(f, "label `{}` is never placed", name),
+      AssemblyError::DuplicateLabel(ref name, _) => {
+        write!(f, "label `{}` is placed more than once", name)
+      }
+    }
+  }
+}
+
+/// An instruction at its place in the bytecode
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct Placed {
+  pub offset: usize,
+  pub opcode: Opcode,
+  /// Immediate data following a PUSH
+  pub data: Vec<u8>,
+  pub span: Option<Span>,
+  pub jump: JumpType,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+  pub items: Vec<Item>,
+  /// Source each item was generated from, where known
+  pub spans: Vec<Option<Span>>,
+  /// Name of each label, for errors about it
+  labels: Vec<String>,
+  span: Option<Span>,
+}
+
+impl Assembly {
+  pub fn new() -> Assembly {
+    Assembly { ..Default::default() }
+  }
+
+  pub fn new_label(&mut self, name: &str) -> Label {
+    self.labels.push(name.to_string());
+    self.labels.len() - 1
+  }
+
+  /// Sets the source that items appended from now on are generated from,
+  /// returning the previous one
+  pub fn set_span(&mut self, span: Option<Span>) -> Option<Span> {
+    mem::replace(&mut self.span, span)
+  }
+
+  pub fn append(&mut self, item: Item) {
+    self.items.push(item);
+    self.spans.push(self.span);
+  }
+
+  pub fn assemble(&self) -> Result<Vec<u8>, AssemblyError> {
+    self.place().map(|instructions| Assembly::encode(&instructions))
+  }
+
+  pub fn encode(instructions: &[Placed]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for instruction in instructions {
+      bytes.push(instruction.opcode.byte());
+      bytes.extend_from_slice(&instruction.data);
+    }
+    bytes
+  }
+
+  /// Lays the items out as instructions, pushing each label with the fewest
+  /// bytes that hold its offset
+  pub fn place(&self) -> Result<Vec<Placed>, AssemblyError> {
+    if let Err(err) = self.check_labels() {
+      return Err(err);
+    }
+
+    // label pushes start out a byte long and only ever grow, moving the
+    // labels after them along, until every offset fits
+    let mut label_sizes = vec![1; self.items.len()];
+    let mut offsets = self.offsets(&label_sizes);
+    loop {
+      let mut grown = false;
+      for (item, size) in self.items.iter().zip(label_sizes.iter_mut()) {
+        if let Item::PushLabel(label) = *item {
+          let needed = Assembly::push_size(U256::from(offsets[label]));
+          if needed > *size {
+            *size = needed;
+            grown = true;
+          }
+        }
+      }
+
+      if !grown {
+        break;
+      }
+      offsets = self.offsets(&label_sizes);
+    }
+
+    let mut instructions = vec![];
+    let mut offset = 0;
+    for ((item, &size), &span) in self.items.iter().zip(&label_sizes).zip(&self.spans) {
+      let (opcode, data) = match *item {
+        Item::Op(op) => (op, vec![]),
+        Item::Jump(_) => (Opcode::JUMP, vec![]),
+        Item::Push(value) => {
+          let size = Assembly::push_size(value);
+          (Opcode::push(size), Assembly::word(value, size))
+        }
+        Item::PushLabel(label) => {
+          (Opcode::push(size), Assembly::word(U256::from(offsets[label]), size))
+        }
+        Item::Label(_) => (Opcode::JUMPDEST, vec![]),
+      };
+
+      let length = 1 + data.len();
+      instructions.push(Placed {
+                          offset: offset,
+                          opcode: opcode,
+                          data: data,
+                          span: span,
+                          jump: match *item {
+                            Item::Jump(jump) => jump,
+                            _ => JumpType::Regular,
+                          },
+                        });
+      offset += length;
+    }
+
+    Ok(instructions)
+  }
+
+  /// Every label pushed must be placed, and only once
+  fn check_labels(&self) -> Result<(), AssemblyError> {
+    let mut placed = vec![false; self.labels.len()];
+    for (item, &span) in self.items.iter().zip(&self.spans) {
+      if let Item::Label(label) = *item {
+        if placed[label] {
+          return Err(AssemblyError::DuplicateLabel(self.labels[label].clone(), span));
+        }
+        placed[label] = true;
+      }
+    }
+
+    for (item, &span) in self.items.iter().zip(&self.spans) {
+      if let Item::PushLabel(label) = *item {
+        if !placed[label] {
+          return Err(AssemblyError::UnresolvedLabel(self.labels[label].clone(), span));
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Offset of each label, given the size of each label push
+  fn offsets(&self, label_sizes: &[usize]) -> Vec<usize> {
+    let mut offsets = vec![0; self.labels.len()];
+    let mut offset = 0;
+    for (item, &size) in self.items.iter().zip(label_sizes) {
+      offset += match *item {
+        Item::Op(_) | Item::Jump(_) => 1,
+        Item::Push(value) => 1 + Assembly::push_size(value),
+        Item::PushLabel(_) => 1 + size,
+        Item::Label(label) => {
+          offsets[label] = offset;
+          1
+        }
+      };
+    }
+
+    offsets
+  }
+
+  /// Smallest number of bytes needed to represent `value`, at least one
+  fn push_size(value: U256) -> usize {
+    let bytes = (value.bits() + 7) / 8;
+    if bytes == 0 { 1 } else { bytes }
+  }
+
+  /// Big-endian bytes of `value`, `size` bytes long
+  fn word(value: U256, size: usize) -> Vec<u8> {
+    let mut word = [0u8; 32];
+    value.to_big_endian(&mut word);
+    word[32 - size..].to_vec()
+  }
+}
+
+#[test]
+fn it_assembles_minimal_pushes() {
+  let mut assembly = Assembly::new();
+  assembly.append(Item::Push(U256::from(0x60)));
+  assembly.append(Item::Push(U256::from(0x40)));
+  assembly.append(Item::Op(Opcode::MSTORE));
+  assembly.append(Item::Push(U256::from(0)));
+  assembly.append(Item::Push(U256::from(0x1234)));
+
+  assert_eq!(assembly.assemble(),
+             Ok(vec![0x60, 0x60, 0x60, 0x40, 0x52, 0x60, 0x00, 0x61, 0x12, 0x34]));
+}
+
+#[test]
+fn it_resolves_labels_to_jumpdest_offsets() {
+  let mut assembly = Assembly::new();
+  let label = assembly.new_label("end");
+  assembly.append(Item::PushLabel(label));
+  assembly.append(Item::Op(Opcode::JUMP));
+  assembly.append(Item::Label(label));
+
+  assert_eq!(assembly.assemble(), Ok(vec![0x60, 0x03, 0x56, 0x5b]));
+}
+
+#[test]
+fn it_grows_label_pushes_until_offsets_fit() {
+  let mut assembly = Assembly::new();
+  let label = assembly.new_label("end");
+  assembly.append(Item::PushLabel(label));
+  assembly.append(Item::Op(Opcode::JUMP));
+  for _ in 0..253 {
+    assembly.append(Item::Op(Opcode::STOP));
+  }
+  assembly.append(Item::Label(label));
+
+  // pushed in one byte, the label would be at 256, which takes two
+  let bytes = assembly.assemble().unwrap();
+  assert_eq!(&bytes[..4], &[0x61, 0x01, 0x01, 0x56]);
+  assert_eq!(bytes.len(), 258);
+  assert_eq!(bytes[257], 0x5b);
+}
+
+#[test]
+fn it_reports_misplaced_labels() {
+  let mut assembly = Assembly::new();
+  let unplaced = assembly.new_label("unplaced");
+  assembly.set_span(Some(Span::new(2, 10)));
+  assembly.append(Item::PushLabel(unplaced));
+  assert_eq!(assembly.assemble(),
+             Err(AssemblyError::UnresolvedLabel("unplaced".to_string(), Some(Span::new(2, 10)))));
+
+  let mut assembly = Assembly::new();
+  let twice = assembly.new_label("twice");
+  assembly.set_span(Some(Span::new(2, 8)));
+  assembly.append(Item::Label(twice));
+  assembly.set_span(Some(Span::new(9, 15)));
+  assembly.append(Item::Label(twice));
+  assert_eq!(assembly.assemble(),
+             Err(AssemblyError::DuplicateLabel("twice".to_string(), Some(Span::new(9, 15)))));
+}
@@ -0,0 +1,974 @@
+//! Code generation from the assembly AST into an EVM instruction stream
+//!
+//! Variables live on the stack, each in the slot it was pushed to, and are
+//! accessed with DUP/SWAP relative to the current stack height. Functions are
+//! called by pushing a return label and the arguments (first argument on top)
+//! and jumping to the function's label; on return, only the return values
+//! remain on the stack.
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::iter;
+use std::mem;
+
+extern crate bigint;
+use self::bigint::U256;
+
+use asm::ast::*;
+use asm::ast::visitor::*;
+use evm::assembly::{Assembly, AssemblyError, Item, JumpType, Label};
+use evm::opcode::Opcode;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodegenError {
+  UnknownIdentifier(Identifier),
+  UnknownFunction(Identifier),
+  Redeclared(Identifier),
+  ArgumentCount(Identifier, usize, usize),
+  ValueCount(usize, usize),
+  StackTooDeep(usize),
+  StackUnderflow(Opcode),
+  NotOnStack(Identifier),
+  MisplacedControlOp(ControlOp),
+  LiteralTooLong(usize),
+  Unsupported(&'static str),
+  Assembly(AssemblyError),
+}
+
+impl fmt::Display for CodegenError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      CodegenError::UnknownIdentifier(ref i) => write!(f, "unknown identifier `{}`", i.symbol),
+      CodegenError::UnknownFunction(ref i) => write!(f, "unknown function `{}`", i.symbol),
+      CodegenError::Redeclared(ref i) => {
+        write!(f, "function `{}` is already declared in this block", i.symbol)
+      }
+      CodegenError::ArgumentCount(ref i, expected, found) => {
+        write!(f, "`{}` expects {} arguments, found {}", i.symbol, expected, found)
+      }
+      CodegenError::ValueCount(expected, found) => {
+        write!(f, "expected {} values, found {}", expected, found)
+      }
+      CodegenError::StackTooDeep(depth) => {
+        write!(f, "stack too deep, cannot reach {} slots down", depth)
+      }
+      CodegenError::StackUnderflow(op) => {
+        write!(f,
+               "`{}` needs {} stack items",
+               op.mnemonic().to_lowercase(),
+               op.inputs())
+      }
+      CodegenError::NotOnStack(ref i) => {
+        write!(f, "`{}` is no longer on the stack", i.symbol)
+      }
+      CodegenError::MisplacedControlOp(ControlOp::Break) => write!(f, "`break` outside of for loop"),
+      CodegenError::MisplacedControlOp(ControlOp::Continue) => {
+        write!(f, "`continue` outside of for loop")
+      }
+      CodegenError::MisplacedControlOp(ControlOp::Leave) => write!(f, "`leave` outside of function"),
+      CodegenError::LiteralTooLong(length) => {
+        write!(f, "literal is {} bytes long, at most 32 are allowed", length)
+      }
+      CodegenError::Unsupported(what) => write!(f, "{} are not supported", what),
+      CodegenError::Assembly(ref err) => write!(f, "{}", err),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+struct Function {
+  label: Label,
+  arguments: usize,
+  returns: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Scope {
+  /// Stack slot of each variable, counting from the bottom starting at 1
+  variables: HashMap<String, usize>,
+  functions: HashMap<String, Function>,
+  labels: HashMap<String, Label>,
+  /// Stack height at the start of the block
+  height: usize,
+  /// Variables whose slots instructions have taken off the stack
+  consumed: HashSet<String>,
+  /// What was consumed in each enclosing scope when the block was entered
+  entry_consumed: Vec<HashSet<String>>,
+  /// Function bodies cannot see variables or labels declared outside of them
+  boundary: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Loop {
+  post: Label,
+  end: Label,
+  height: usize,
+}
+
+/// Where `leave` jumps to in the function being generated, i.e. the code
+/// that returns from it
+#[derive(Debug, Clone)]
+struct Exit {
+  label: Label,
+  height: usize,
+  /// The label is only placed when jumped to
+  used: bool,
+}
+
+pub struct CodeGenerator {
+  assembly: Assembly,
+  height: usize,
+  scopes: Vec<Scope>,
+  loops: Vec<Loop>,
+  exit: Option<Exit>,
+  error: Option<(Span, CodegenError)>,
+}
+
+impl CodeGenerator {
+  /// Generates code for `block`, or the first error along with the span of
+  /// the offending node
+  pub fn generate(block: &Node<Block>) -> Result<Assembly, (Span, CodegenError)> {
+    let mut generator = CodeGenerator {
+      assembly: Assembly::new(),
+      height: 0,
+      scopes: vec![],
+      loops: vec![],
+      exit: None,
+      error: None,
+    };
+    generator.visit_block(block);
+
+    match generator.error {
+      Some(err) => Err(err),
+      None => Ok(generator.assembly),
+    }
+  }
+
+  fn fail(&mut self, span: Span, err: CodegenError) {
+    if self.error.is_none() {
+      self.error = Some((span, err));
+    }
+  }
+
+  fn failed(&self) -> bool {
+    self.error.is_some()
+  }
+
+  fn emit(&mut self, op: Opcode) {
+    self.assembly.append(Item::Op(op));
+    self.height = self.height + op.outputs() - op.inputs();
+  }
+
+  fn push(&mut self, value: U256) {
+    self.assembly.append(Item::Push(value));
+    self.height += 1;
+  }
+
+  fn push_bytes(&mut self, span: Span, bytes: &[u8]) {
+    if bytes.len() > 32 {
+      self.fail(span, CodegenError::LiteralTooLong(bytes.len()));
+      return;
+    }
+
+    let mut word = [0u8; 32];
+    word[..bytes.len()].copy_from_slice(bytes);
+    self.push(U256::from(&word[..]));
+  }
+
+  fn push_label(&mut self, label: Label) {
+    self.assembly.append(Item::PushLabel(label));
+    self.height += 1;
+  }
+
+  fn label(&mut self, label: Label) {
+    self.assembly.append(Item::Label(label));
+  }
+
+  fn jump(&mut self, label: Label) {
+    self.push_label(label);
+    self.emit(Opcode::JUMP);
+  }
+
+  fn call(&mut self, label: Label) {
+    self.push_label(label);
+    self.assembly.append(Item::Jump(JumpType::IntoFunction));
+    self.height -= 1;
+  }
+
+  fn dup(&mut self, identifier: &Node<Identifier>, slot: usize) {
+    if self.consumed(&identifier.symbol) || slot > self.height {
+      self.fail(identifier.span, CodegenError::NotOnStack((**identifier).clone()));
+      return;
+    }
+
+    let depth = self.height - slot + 1;
+    if depth > 16 {
+      self.fail(identifier.span, CodegenError::StackTooDeep(depth));
+    } else {
+      self.emit(Opcode::dup(depth));
+    }
+  }
+
+  /// Moves the value on top of the stack into the variable's slot
+  fn assign(&mut self, identifier: &Node<Identifier>, slot: usize) {
+    if self.consumed(&identifier.symbol) || slot > self.height {
+      self.fail(identifier.span, CodegenError::NotOnStack((**identifier).clone()));
+      return;
+    }
+
+    match self.height - slot {
+      0 => self.fail(identifier.span, CodegenError::ValueCount(1, 0)),
+      depth if depth > 16 => self.fail(identifier.span, CodegenError::StackTooDeep(depth + 1)),
+      depth => {
+        self.emit(Opcode::swap(depth));
+        self.emit(Opcode::POP);
+      }
+    }
+  }
+
+  fn swap(&mut self, span: Span, depth: usize) {
+    if depth > 16 {
+      self.fail(span, CodegenError::StackTooDeep(depth + 1));
+    } else {
+      self.emit(Opcode::swap(depth));
+    }
+  }
+
+  fn pop_to(&mut self, height: usize) {
+    while self.height > height {
+      self.emit(Opcode::POP);
+    }
+  }
+
+  fn declare(&mut self, identifier: &Node<Identifier>, slot: usize) {
+    let scope = self.scopes.last_mut().unwrap();
+    scope.variables.insert(identifier.symbol.clone(), slot);
+    scope.consumed.remove(&identifier.symbol);
+  }
+
+  /// Marks the variables in the top `count` stack slots as taken off the
+  /// stack, so that they can't be used any more
+  fn consume(&mut self, count: usize) {
+    let (lowest, highest) = (self.height + 1 - count, self.height);
+    for scope in self.scopes.iter_mut().rev() {
+      let symbols: Vec<String> = scope.variables
+        .iter()
+        .filter(|&(_, &slot)| slot >= lowest && slot <= highest)
+        .map(|(symbol, _)| symbol.clone())
+        .collect();
+      scope.consumed.extend(symbols);
+
+      if scope.boundary {
+        break;
+      }
+    }
+  }
+
+  fn consumed(&self, symbol: &str) -> bool {
+    for scope in self.scopes.iter().rev() {
+      if scope.variables.contains_key(symbol) {
+        return scope.consumed.contains(symbol);
+      }
+
+      if scope.boundary {
+        break;
+      }
+    }
+
+    false
+  }
+
+  /// Height of the variables in scope, below the values a label's stack
+  /// counts from
+  fn base(&self) -> usize {
+    let scope = self.scopes.last().unwrap();
+    scope.variables.values().cloned().fold(scope.height, cmp::max)
+  }
+
+  fn open_scope(&mut self, block: &Node<Block>, height: usize) {
+    let consumed = self.scopes.iter().map(|scope| scope.consumed.clone()).collect();
+    self.scopes.push(Scope {
+                       height: height,
+                       entry_consumed: consumed,
+                       ..Default::default()
+                     });
+    self.declare_hoisted(block);
+  }
+
+  /// Pops what the block left on the stack, unless it ends by jumping away
+  /// and never reaches its end, in which case the code after it sees the
+  /// stack as it was before the block
+  fn close_scope(&mut self, block: &Node<Block>, height: usize) {
+    if block.statements.last().map_or(false, |s| self.jumps_away(s)) {
+      self.height = height;
+      let scope = self.scopes.pop().unwrap();
+      for (outer, consumed) in self.scopes.iter_mut().zip(scope.entry_consumed) {
+        outer.consumed = consumed;
+      }
+    } else {
+      self.pop_to(height);
+      self.scopes.pop();
+    }
+  }
+
+  fn jumps_away(&self, statement: &Statement) -> bool {
+    match *statement {
+      Statement::ControlOp(_) => true,
+      Statement::Instruction(ref i) => i.opcode == Opcode::JUMP,
+      Statement::Expression(ref e) => {
+        match **e {
+          Expression::FunctionCall(ref c) => {
+            self.lookup_function(&c.identifier.symbol).is_none() &&
+            Opcode::builtin(&c.identifier.symbol) == Some(Opcode::JUMP)
+          }
+          _ => false,
+        }
+      }
+      _ => false,
+    }
+  }
+
+  /// Visits the statements of a block. The variables of a label's stack are
+  /// declared in the block following it, or in the rest of this one if no
+  /// block follows
+  fn visit_statements<'v>(&mut self, statements: &'v [Node<Statement>]) {
+    let mut items: Option<&'v Vec<Node<Identifier>>> = None;
+    for statement in statements {
+      if self.failed() {
+        return;
+      }
+
+      match (items.take(), &**statement) {
+        (Some(items), &Statement::Block(ref block)) => {
+          let span = self.assembly.set_span(Some(statement.span));
+          self.visit_labelled_block(block, items);
+          self.assembly.set_span(span);
+          continue;
+        }
+        (Some(items), _) => self.declare_items(items),
+        (None, _) => {}
+      }
+
+      self.visit_statement(statement);
+      if let Statement::LabelDefinition(ref label) = **statement {
+        items = label.items();
+      }
+    }
+
+    if let Some(items) = items {
+      self.declare_items(items);
+    }
+  }
+
+  /// Declares the variables for the values on top of the stack
+  fn declare_items(&mut self, items: &[Node<Identifier>]) {
+    let height = self.height - items.len();
+    for (i, item) in items.iter().enumerate() {
+      self.declare(item, height + 1 + i);
+    }
+  }
+
+  fn visit_labelled_block(&mut self, b: &Node<Block>, items: &[Node<Identifier>]) {
+    // the block starts below the values the label declares
+    let height = self.height - items.len();
+    self.open_scope(b, height);
+    self.declare_items(items);
+    self.visit_statements(&b.statements);
+    self.close_scope(b, height);
+  }
+
+  /// Functions and labels are visible throughout the block they are defined
+  /// in, including before their definition
+  fn declare_hoisted(&mut self, block: &Node<Block>) {
+    for statement in &block.statements {
+      if let Statement::LabelDefinition(ref definition) = **statement {
+        let symbol = definition.identifier.symbol.clone();
+        let label = self.assembly.new_label(&symbol);
+        self.scopes.last_mut().unwrap().labels.insert(symbol, label);
+      }
+
+      if let Statement::FunctionDefinition(ref definition) = **statement {
+        let function = Function {
+          label: self.assembly.new_label(&definition.identifier.symbol),
+          arguments: definition.arguments.len(),
+          returns: definition.returns.as_ref().map_or(0, |returns| returns.len()),
+        };
+
+        let symbol = definition.identifier.symbol.clone();
+        let redeclared = self.scopes.last_mut().unwrap().functions.insert(symbol, function);
+        if redeclared.is_some() {
+          self.fail(definition.identifier.span,
+                    CodegenError::Redeclared((*definition.identifier).clone()));
+        }
+      }
+    }
+  }
+
+  fn lookup_variable(&self, symbol: &str) -> Option<usize> {
+    for scope in self.scopes.iter().rev() {
+      if let Some(&slot) = scope.variables.get(symbol) {
+        return Some(slot);
+      }
+
+      if scope.boundary {
+        break;
+      }
+    }
+
+    None
+  }
+
+  fn lookup_label(&self, symbol: &str) -> Option<Label> {
+    for scope in self.scopes.iter().rev() {
+      if let Some(&label) = scope.labels.get(symbol) {
+        return Some(label);
+      }
+
+      if scope.boundary {
+        break;
+      }
+    }
+
+    None
+  }
+
+  fn lookup_function(&self, symbol: &str) -> Option<Function> {
+    self.scopes.iter().rev().filter_map(|scope| scope.functions.get(symbol)).next().cloned()
+  }
+
+  /// Visits an expression that must produce exactly one value
+  fn visit_value(&mut self, e: &Node<Expression>) {
+    let height = self.height;
+    self.visit_expression(e);
+
+    if !self.failed() && self.height != height + 1 {
+      self.fail(e.span, CodegenError::ValueCount(1, self.height - height));
+    }
+  }
+
+  /// Rearranges the stack from `[return label, arguments, return values]`
+  /// into `[return values, return label]` and jumps back to the caller
+  fn leave_function(&mut self, span: Span, arguments: usize, returns: usize) {
+    for step in return_shuffle(arguments, returns) {
+      if self.failed() {
+        return;
+      }
+
+      match step {
+        Some(depth) => self.swap(span, depth),
+        None => self.emit(Opcode::POP),
+      }
+    }
+
+    self.assembly.append(Item::Jump(JumpType::OutOfFunction));
+    self.height -= 1;
+  }
+}
+
+/// Stack operations that turn `[return label, arguments, return values]` into
+/// `[return values, return label]`: `Some(depth)` swaps the top item with the
+/// one `depth` below it and `None` pops it
+pub fn return_shuffle(arguments: usize, returns: usize) -> Vec<Option<usize>> {
+  // target position of each stack slot, or None if it is discarded
+  let mut layout: Vec<Option<usize>> = vec![Some(returns)];
+  layout.extend(iter::repeat(None).take(arguments));
+  layout.extend((0..returns).map(Some));
+
+  let mut steps = vec![];
+  while let Some(&last) = layout.last() {
+    let top = layout.len() - 1;
+    match last {
+      Some(target) if target == top => break,
+      Some(target) => {
+        steps.push(Some(top - target));
+        layout.swap(target, top);
+      }
+      None => {
+        steps.push(None);
+        layout.pop();
+      }
+    }
+  }
+
+  steps
+}
+
+impl<'v> Visitor<'v> for CodeGenerator {
+  fn visit_block(&mut self, b: &'v Node<Block>) {
+    if self.failed() {
+      return;
+    }
+
+    let height = self.height;
+    self.open_scope(b, height);
+    self.visit_statements(&b.statements);
+    self.close_scope(b, height);
+  }
+
+  fn visit_statement(&mut self, s: &'v Node<Statement>) {
+    if self.failed() {
+      return;
+    }
+
+    let span = self.assembly.set_span(Some(s.span));
+    s.walk(self);
+    self.assembly.set_span(span);
+  }
+
+  fn visit_expression(&mut self, e: &'v Node<Expression>) {
+    let span = self.assembly.set_span(Some(e.span));
+    match **e {
+      Expression::Identifier(ref identifier) => {
+        match self.lookup_variable(&identifier.symbol) {
+          Some(slot) => self.dup(identifier, slot),
+          None => {
+            match self.lookup_label(&identifier.symbol) {
+              Some(label) => self.push_label(label),
+              None => {
+                self.fail(identifier.span,
+                          CodegenError::UnknownIdentifier((**identifier).clone()))
+              }
+            }
+          }
+        }
+      }
+      _ => e.walk(self),
+    }
+    self.assembly.set_span(span);
+  }
+
+  fn visit_function_definition(&mut self, f: &'v Node<FunctionDefinition>) {
+    let FunctionDefinition { ref identifier, ref arguments, ref returns, ref body } = **f;
+    let function = self.lookup_function(&identifier.symbol).unwrap();
+
+    let skip = self.assembly.new_label("$function_end");
+    self.jump(skip);
+    self.label(function.label);
+
+    let height = self.height;
+    let loops = mem::replace(&mut self.loops, vec![]);
+    self.scopes.push(Scope { boundary: true, ..Default::default() });
+
+    // return label, then the arguments with the first one on top
+    self.height = 1 + arguments.len();
+    for (i, argument) in arguments.iter().enumerate() {
+      self.declare(argument, 1 + arguments.len() - i);
+    }
+
+    if let Some(ref returns) = *returns {
+      for identifier in returns {
+        self.push(U256::from(0));
+        let slot = self.height;
+        self.declare(identifier, slot);
+      }
+    }
+
+    let exit = Exit {
+      label: self.assembly.new_label("$function_exit"),
+      height: self.height,
+      used: false,
+    };
+    let outer_exit = mem::replace(&mut self.exit, Some(exit));
+
+    self.visit_block(body);
+    match mem::replace(&mut self.exit, outer_exit) {
+      Some(Exit { label, used: true, .. }) => self.label(label),
+      _ => {}
+    }
+    self.leave_function(f.span, function.arguments, function.returns);
+
+    self.scopes.pop();
+    self.loops = loops;
+    self.height = height;
+    self.label(skip);
+  }
+
+  fn visit_variable_declaration(&mut self, v: &'v Node<VariableDeclaration>) {
+    let VariableDeclaration { ref identifiers, ref expression } = **v;
+
+    let height = self.height;
+    self.visit_expression(expression);
+    if self.failed() {
+      return;
+    }
+
+    let values = self.height - height;
+    if values != identifiers.len() {
+      self.fail(v.span, CodegenError::ValueCount(identifiers.len(), values));
+      return;
+    }
+
+    for (i, identifier) in identifiers.iter().enumerate() {
+      self.declare(identifier, height + 1 + i);
+    }
+  }
+
+  fn visit_assignment(&mut self, a: &'v Node<Assignment>) {
+    let Assignment { ref identifiers, ref expression } = **a;
+
+    let height = self.height;
+    self.visit_expression(expression);
+    if self.failed() {
+      return;
+    }
+
+    let values = self.height - height;
+    if values != identifiers.len() {
+      self.fail(a.span, CodegenError::ValueCount(identifiers.len(), values));
+      return;
+    }
+
+    // the last identifier's value is on top of the stack
+    for identifier in identifiers.iter().rev() {
+      match self.lookup_variable(&identifier.symbol) {
+        Some(slot) => self.assign(identifier, slot),
+        None => {
+          self.fail(identifier.span,
+                    CodegenError::UnknownIdentifier((**identifier).clone()))
+        }
+      }
+    }
+  }
+
+  fn visit_if(&mut self, i: &'v Node<If>) {
+    let If { ref condition, ref body } = **i;
+
+    let end = self.assembly.new_label("$if_end");
+    self.visit_value(condition);
+    self.emit(Opcode::ISZERO);
+    self.push_label(end);
+    self.emit(Opcode::JUMPI);
+
+    self.visit_block(body);
+    self.label(end);
+  }
+
+  fn visit_switch(&mut self, s: &'v Node<Switch>) {
+    let Switch { ref expression, ref cases, ref default } = **s;
+
+    self.visit_value(expression);
+    let height = self.height;
+
+    let end = self.assembly.new_label("$switch_end");
+    let labels: Vec<Label> =
+      cases.iter().map(|_| self.assembly.new_label("$switch_case")).collect();
+
+    for (case, &label) in cases.iter().zip(&labels) {
+      self.visit_value(&case.expression);
+      self.emit(Opcode::DUP2);
+      self.emit(Opcode::EQ);
+      self.push_label(label);
+      self.emit(Opcode::JUMPI);
+    }
+
+    if let Some(ref block) = *default {
+      self.visit_block(block);
+    }
+    self.jump(end);
+
+    for (case, &label) in cases.iter().zip(&labels) {
+      self.label(label);
+      self.visit_block(&case.block);
+      self.jump(end);
+    }
+
+    self.label(end);
+    self.height = height;
+    self.emit(Opcode::POP);
+  }
+
+  fn visit_for_loop(&mut self, f: &'v Node<ForLoop>) {
+    let ForLoop { ref init, ref condition, ref post, ref body } = **f;
+
+    // variables declared in the init block stay in scope for the whole loop
+    let height = self.height;
+    self.open_scope(init, height);
+    self.visit_statements(&init.statements);
+
+    let start = self.assembly.new_label("$for_begin");
+    let next = self.assembly.new_label("$for_continue");
+    let end = self.assembly.new_label("$for_end");
+
+    self.label(start);
+    self.visit_value(condition);
+    self.emit(Opcode::ISZERO);
+    self.push_label(end);
+    self.emit(Opcode::JUMPI);
+
+    self.loops.push(Loop {
+                      post: next,
+                      end: end,
+                      height: self.height,
+                    });
+    self.visit_block(body);
+    self.loops.pop();
+
+    self.label(next);
+    self.visit_block(post);
+    self.jump(start);
+
+    self.label(end);
+    self.pop_to(height);
+    self.scopes.pop();
+  }
+
+  fn visit_control_op(&mut self, o: &'v Node<ControlOp>) {
+    let target = match (&**o, self.loops.last(), self.exit.as_mut()) {
+      (&ControlOp::Break, Some(target), _) => Some((target.end, target.height)),
+      (&ControlOp::Continue, Some(target), _) => Some((target.post, target.height)),
+      (&ControlOp::Leave, _, Some(exit)) => {
+        exit.used = true;
+        Some((exit.label, exit.height))
+      }
+      _ => None,
+    };
+
+    let (label, target_height) = match target {
+      Some(target) => target,
+      None => {
+        self.fail(o.span, CodegenError::MisplacedControlOp((**o).clone()));
+        return;
+      }
+    };
+
+    let height = self.height;
+    self.pop_to(target_height);
+    self.jump(label);
+    self.height = height;
+  }
+
+  fn visit_sub_assembly(&mut self, a: &'v Node<SubAssembly>) {
+    self.fail(a.span, CodegenError::Unsupported("sub-assemblies"));
+  }
+
+  fn visit_label_definition(&mut self, l: &'v Node<LabelDefinition>) {
+    let label = self.lookup_label(&l.identifier.symbol).unwrap();
+    self.label(label);
+
+    if let Some(ref stack) = l.stack {
+      self.height = self.base() + stack.len();
+    }
+  }
+
+  fn visit_instruction(&mut self, i: &'v Node<Instruction>) {
+    if i.opcode.inputs() > self.height {
+      self.fail(i.span, CodegenError::StackUnderflow(i.opcode));
+    } else {
+      self.consume(i.opcode.inputs());
+      self.emit(i.opcode);
+    }
+  }
+
+  fn visit_stack_assignment(&mut self, a: &'v Node<StackAssignment>) {
+    let identifier = &a.identifier;
+    match self.lookup_variable(&identifier.symbol) {
+      Some(slot) if slot == self.height && !self.consumed(&identifier.symbol) => {
+        self.fail(a.span, CodegenError::ValueCount(1, 0))
+      }
+      Some(slot) => {
+        // whatever is in the top slot is moved down and no longer there
+        self.consume(1);
+        self.assign(identifier, slot);
+      }
+      None => {
+        self.fail(identifier.span,
+                  CodegenError::UnknownIdentifier((**identifier).clone()))
+      }
+    }
+  }
+
+  fn visit_function_call(&mut self, c: &'v Node<FunctionCall>) {
+    let FunctionCall { ref identifier, ref arguments } = **c;
+
+    // functions declared in the program take precedence over builtins
+    if let Some(function) = self.lookup_function(&identifier.symbol) {
+      if arguments.len() != function.arguments {
+        self.fail(c.span,
+                  CodegenError::ArgumentCount((**identifier).clone(),
+                                              function.arguments,
+                                              arguments.len()));
+        return;
+      }
+
+      let height = self.height;
+      let ret = self.assembly.new_label("$funcall_return");
+      self.push_label(ret);
+      for argument in arguments.iter().rev() {
+        self.visit_value(argument);
+      }
+      self.call(function.label);
+      self.label(ret);
+      self.height = height + function.returns;
+    } else if let Some(op) = Opcode::builtin(&identifier.symbol) {
+      if arguments.len() != op.inputs() {
+        self.fail(c.span,
+                  CodegenError::ArgumentCount((**identifier).clone(), op.inputs(), arguments.len()));
+        return;
+      }
+
+      for argument in arguments.iter().rev() {
+        self.visit_value(argument);
+      }
+      if self.failed() {
+        return;
+      }
+      self.emit(op);
+    } else {
+      self.fail(identifier.span, CodegenError::UnknownFunction((**identifier).clone()));
+    }
+  }
+
+  fn visit_string_literal(&mut self, s: &'v Node<StringLiteral>) {
+    self.push_bytes(s.span, &s.bytes);
+  }
+
+  fn visit_hex_literal(&mut self, x: &'v Node<HexLiteral>) {
+    self.push_bytes(x.span, &x.bytes);
+  }
+
+  fn visit_hex_number(&mut self, x: &'v Node<HexNumber>) {
+    self.push(x.uint);
+  }
+
+  fn visit_dec_number(&mut self, n: &'v Node<DecNumber>) {
+    self.push(n.uint);
+  }
+}
+
+#[cfg(test)]
+use asm::grammar;
+
+#[cfg(test)]
+extern crate rustc_serialize;
+
+#[cfg(test)]
+use self::rustc_serialize::hex::ToHex;
+
+#[cfg(test)]
+fn generate(program: &str) -> Result<String, CodegenError> {
+  let block = grammar::block(program).unwrap();
+  CodeGenerator::generate(&block)
+    .map(|assembly| assembly.assemble().unwrap().to_hex())
+    .map_err(|(_, err)| err)
+}
+
+#[test]
+fn it_generates_builtin_calls() {
+  assert_eq!(generate("{ mstore(0x40, 0x60) }"), Ok("6060604052".to_string()));
+}
+
+#[test]
+fn it_generates_variable_access() {
+  assert_eq!(generate("{ let x := 1 x := add(x, 2) }"),
+             Ok("600160028101905050".to_string()));
+}
+
+#[test]
+fn it_generates_function_calls() {
+  assert_eq!(generate("{ function f(a) -> (b) { b := a } let x := f(7) }"),
+             Ok("600d565b6000819050919050565b601560076003565b50".to_string()));
+}
+
+#[test]
+fn it_prefers_functions_to_builtins() {
+  let shadowing = generate("{ function add(a, b) -> c { c := sub(a, b) } pop(add(3, 1)) }");
+  let renamed = generate("{ function f(a, b) -> c { c := sub(a, b) } pop(f(3, 1)) }");
+
+  assert!(shadowing.is_ok());
+  assert_eq!(shadowing, renamed);
+}
+
+#[test]
+fn it_generates_leave() {
+  assert_eq!(generate("{ function f(a) -> (b) { let c := 2 if a { leave } b := c } }"),
+             Ok("601b565b600060028215601157506016565b809150505b919050565b".to_string()));
+  assert_eq!(generate("{ leave }"),
+             Err(CodegenError::MisplacedControlOp(ControlOp::Leave)));
+}
+
+#[test]
+fn it_generates_labels() {
+  assert_eq!(generate("{ start: jump(start) }"), Ok("5b600056".to_string()));
+}
+
+#[test]
+fn it_generates_label_stacks() {
+  assert_eq!(generate("{ jump(l) l[1]: pop }"), Ok("6003565b50".to_string()));
+  assert_eq!(generate("{ 7 jump(l) l[a]: { sstore(0, a) } }"),
+             Ok("60076005565b8060005550".to_string()));
+  assert_eq!(generate("{ jump(l) l: pop }"), Err(CodegenError::StackUnderflow(Opcode::POP)));
+
+  // nothing is popped after a block's final jump
+  assert_eq!(generate("{ let x := 1 jump(0) }"), Ok("6001600056".to_string()));
+}
+
+#[test]
+fn it_generates_ifs() {
+  assert_eq!(generate("{ if 1 { pop(2) } }"),
+             Ok("6001156009576002505b".to_string()));
+}
+
+#[test]
+fn it_generates_instructions() {
+  assert_eq!(generate("{ 0x60 0x40 mstore }"), Ok("6060604052".to_string()));
+  assert_eq!(generate("{ let x := 0 1 =: x }"), Ok("60006001905050".to_string()));
+  assert_eq!(generate("{ pop }"), Err(CodegenError::StackUnderflow(Opcode::POP)));
+}
+
+#[test]
+fn it_rejects_variables_taken_off_the_stack() {
+  let x = Identifier { symbol: "x".to_string(), type_name: None };
+  let y = Identifier { symbol: "y".to_string(), type_name: None };
+
+  assert_eq!(generate("{ let x := 1 pop pop(x) }"),
+             Err(CodegenError::NotOnStack(x.clone())));
+  assert_eq!(generate("{ let x := 1 pop x := 2 }"),
+             Err(CodegenError::NotOnStack(x.clone())));
+  assert_eq!(generate("{ let x := 1 pop 2 =: x }"),
+             Err(CodegenError::NotOnStack(x.clone())));
+  assert_eq!(generate("{ let x := 1 let y := 2 =: x pop(y) }"),
+             Err(CodegenError::NotOnStack(y)));
+  assert_eq!(generate("{ let x := 1 =: x }"), Err(CodegenError::ValueCount(1, 0)));
+
+  // a block jumping away leaves the variables to the code after it
+  assert!(generate("{ let x := 1 if x { pop jump(0) } pop(x) }").is_ok());
+  assert!(generate("{ let x := 1 pop let x := 2 pop(x) }").is_ok());
+}
+
+#[test]
+fn it_generates_loops_and_switches() {
+  let program = r#"{
+    let y := 0
+    for { let i := 0 } lt(i, 5) { i := add(i, 1) } {
+      switch i
+      case 3: { break }
+      default: { y := add(y, i) }
+    }
+    sstore(0, y)
+  }"#;
+  assert!(generate(program).is_ok());
+}
+
+#[test]
+fn it_reports_codegen_errors() {
+  let i = Identifier { symbol: "i".to_string(), type_name: None };
+  let f = Identifier { symbol: "f".to_string(), type_name: None };
+  let mstore = Identifier { symbol: "mstore".to_string(), type_name: None };
+
+  assert_eq!(generate("{ i }"), Err(CodegenError::UnknownIdentifier(i)));
+  assert_eq!(generate("{ f() }"), Err(CodegenError::UnknownFunction(f)));
+  assert_eq!(generate("{ mstore(0) }"), Err(CodegenError::ArgumentCount(mstore, 2, 1)));
+  assert_eq!(generate("{ let (a, b) := 0 }"), Err(CodegenError::ValueCount(2, 1)));
+  assert_eq!(generate("{ break }"),
+             Err(CodegenError::MisplacedControlOp(ControlOp::Break)));
+}
+
+#[test]
+fn it_locates_codegen_errors() {
+  let program = "{\n  let x := 0\n  x := frobinate(x)\n}";
+  let block = grammar::block(program).unwrap();
+  let (span, _) = CodeGenerator::generate(&block).err().unwrap();
+
+  assert_eq!(&program[span.start..span.end], "frobinate");
+  assert_eq!(span.position(program), Position { line: 3, column: 8 });
+}
@@ -0,0 +1,190 @@
+//! Decoding of EVM bytecode back into instructions
+use std::cmp;
+use std::io::Write;
+
+extern crate rustc_serialize;
+use self::rustc_serialize::hex::ToHex;
+
+use evm::opcode::Opcode;
+
+/// An instruction read from bytecode
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct Decoded {
+  pub offset: usize,
+  pub byte: u8,
+  /// None for bytes that aren't assigned an instruction
+  pub opcode: Option<Opcode>,
+  /// Immediate data following a PUSH, cut short if the code ends first
+  pub data: Vec<u8>,
+}
+
+impl Decoded {
+  /// Number of immediate bytes missing at the end of the code
+  pub fn missing(&self) -> usize {
+    match self.opcode.and_then(|op| op.is_push()) {
+      Some(size) => size - self.data.len(),
+      None => 0,
+    }
+  }
+
+  /// Target of a constant jump, i.e. a JUMP or JUMPI right after a PUSH
+  fn jump_target(&self, previous: Option<&Decoded>) -> Option<usize> {
+    let jumps = self.opcode == Some(Opcode::JUMP) || self.opcode == Some(Opcode::JUMPI);
+    match previous {
+      Some(push) if jumps && push.opcode.and_then(|op| op.is_push()).is_some() &&
+                    push.missing() == 0 && push.data.len() <= 8 => {
+        Some(push.data.iter().fold(0, |target, &byte| (target << 8) | byte as usize))
+      }
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct Disassembly {
+  pub instructions: Vec<Decoded>,
+  /// CBOR encoded metadata solc appends to the code
+  pub metadata: Option<Vec<u8>>,
+}
+
+impl Disassembly {
+  pub fn decode(bytes: &[u8]) -> Disassembly {
+    let (code, metadata) = match Disassembly::metadata_length(bytes) {
+      Some(length) => {
+        let (code, metadata) = bytes.split_at(bytes.len() - length);
+        (code, Some(metadata.to_vec()))
+      }
+      None => (bytes, None),
+    };
+
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset < code.len() {
+      let byte = code[offset];
+      let opcode = Opcode::from_byte(byte);
+      let size = opcode.and_then(|op| op.is_push()).unwrap_or(0);
+      let end = cmp::min(offset + 1 + size, code.len());
+
+      instructions.push(Decoded {
+                          offset: offset,
+                          byte: byte,
+                          opcode: opcode,
+                          data: code[offset + 1..end].to_vec(),
+                        });
+      offset = end;
+    }
+
+    Disassembly {
+      instructions: instructions,
+      metadata: metadata,
+    }
+  }
+
+  /// Length of the metadata at the end of the code, including the two bytes
+  /// giving its length. solc's metadata is a CBOR map, so this only counts
+  /// as metadata if it starts like one
+  fn metadata_length(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < 2 {
+      return None;
+    }
+
+    let length = ((bytes[bytes.len() - 2] as usize) << 8) | bytes[bytes.len() - 1] as usize;
+    if length == 0 || length + 2 > bytes.len() {
+      return None;
+    }
+
+    let start = bytes.len() - 2 - length;
+    match bytes[start] {
+      map if map >= 0xa1 && map <= 0xb7 => Some(length + 2),
+      _ => None,
+    }
+  }
+}
+
+pub struct DisassemblyPrinter {}
+
+impl DisassemblyPrinter {
+  /// Writes an instruction per line with its offset, along with where
+  /// constant jumps go
+  ///
+  /// ```text
+  /// 0000 PUSH1 0x04
+  /// 0002 JUMP // to 0004
+  /// 0003 STOP
+  /// 0004 JUMPDEST
+  /// ```
+  pub fn print<W: Write>(disassembly: &Disassembly, out: &mut W) {
+    let jumpdests: Vec<usize> = disassembly.instructions
+      .iter()
+      .filter(|instruction| instruction.opcode == Some(Opcode::JUMPDEST))
+      .map(|instruction| instruction.offset)
+      .collect();
+
+    let mut previous = None;
+    for instruction in &disassembly.instructions {
+      match instruction.opcode {
+        Some(op) => write!(out, "{:04x} {}", instruction.offset, op).ok(),
+        None => write!(out, "{:04x} INVALID 0x{:02x}", instruction.offset, instruction.byte).ok(),
+      };
+
+      if !instruction.data.is_empty() {
+        write!(out, " 0x{}", instruction.data.to_hex()).ok();
+      }
+
+      if instruction.missing() > 0 {
+        write!(out, " // truncated, {} bytes missing", instruction.missing()).ok();
+      }
+
+      if let Some(target) = instruction.jump_target(previous) {
+        write!(out, " // to {:04x}", target).ok();
+        if !jumpdests.contains(&target) {
+          write!(out, ", not a JUMPDEST").ok();
+        }
+      }
+
+      write!(out, "\n").ok();
+      previous = Some(instruction);
+    }
+
+    if let Some(ref metadata) = disassembly.metadata {
+      let offset = disassembly.instructions
+        .last()
+        .map_or(0, |last| last.offset + 1 + last.data.len());
+      write!(out, "{:04x} // metadata 0x{}\n", offset, metadata.to_hex()).ok();
+    }
+  }
+}
+
+#[cfg(test)]
+use self::rustc_serialize::hex::FromHex;
+
+#[cfg(test)]
+fn disassemble(hex: &str) -> String {
+  let disassembly = Disassembly::decode(&hex.from_hex().unwrap());
+
+  let mut out = Vec::new();
+  DisassemblyPrinter::print(&disassembly, &mut out);
+  String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn it_disassembles_instructions_and_jumps() {
+  assert_eq!(disassemble("6004560c5b61ff"),
+             "0000 PUSH1 0x04\n0002 JUMP // to 0004\n0003 INVALID 0x0c\n0004 JUMPDEST\n\
+              0005 PUSH2 0xff // truncated, 1 bytes missing\n");
+  assert_eq!(disassemble("600356"),
+             "0000 PUSH1 0x03\n0002 JUMP // to 0003, not a JUMPDEST\n");
+}
+
+#[test]
+fn it_separates_metadata() {
+  // {"bzzr0": <32 byte hash>}, followed by its length
+  let metadata = format!("a165627a7a72305820{}0029", "00".repeat(32));
+  let disassembly = Disassembly::decode(&format!("6001{}", metadata).from_hex().unwrap());
+
+  assert_eq!(disassembly.instructions.len(), 1);
+  assert_eq!(disassembly.metadata.unwrap().to_hex(), metadata);
+
+  // a length that doesn't lead to a CBOR map is just code
+  assert_eq!(Disassembly::decode(&[0x00, 0x00, 0x01]).metadata, None);
+}
@@ -0,0 +1,81 @@
+//! Human readable listing of the assembled instructions, one per line with
+//! its byte offset, and the source line they come from as a comment
+//!
+//! ```text
+//! // 1: { mstore(0x40, 0x60) }
+//! 0000 PUSH1 0x60
+//! 0002 PUSH1 0x40
+//! 0004 MSTORE
+//! ```
+use std::io::Write;
+
+extern crate rustc_serialize;
+use self::rustc_serialize::hex::ToHex;
+
+use evm::assembly::Placed;
+
+pub struct ListingPrinter {}
+
+impl ListingPrinter {
+  pub fn print<W: Write>(instructions: &[Placed], source: &str, out: &mut W) {
+    let mut last_line = None;
+
+    for instruction in instructions {
+      if let Some(span) = instruction.span {
+        let line = span.position(source).line;
+        if last_line != Some(line) {
+          let text = source.lines().nth(line - 1).unwrap_or("");
+          write!(out, "// {}: {}\n", line, text.trim()).ok();
+          last_line = Some(line);
+        }
+      }
+
+      write!(out, "{:04x} {}", instruction.offset, instruction.opcode).ok();
+      if !instruction.data.is_empty() {
+        write!(out, " 0x{}", instruction.data.to_hex()).ok();
+      }
+      write!(out, "\n").ok();
+    }
+  }
+}
+
+#[cfg(test)]
+use asm::grammar;
+
+#[cfg(test)]
+use evm::codegen::CodeGenerator;
+
+#[cfg(test)]
+fn list(program: &str) -> String {
+  let block = grammar::block(program).unwrap();
+  let instructions = CodeGenerator::generate(&block).unwrap().place().unwrap();
+
+  let mut out = Vec::new();
+  ListingPrinter::print(&instructions, program, &mut out);
+  String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn it_lists_instructions_with_offsets() {
+  assert_eq!(list("{ mstore(0x40, 0x60) }"),
+             "// 1: { mstore(0x40, 0x60) }\n0000 PUSH1 0x60\n0002 PUSH1 0x40\n0004 MSTORE\n");
+}
+
+#[test]
+fn it_comments_each_source_line() {
+  let program = "{\n  let x := 1\n  if x { x := 0x0102 }\n}";
+  assert_eq!(list(program),
+             r#"// 2: let x := 1
+0000 PUSH1 0x01
+// 3: if x { x := 0x0102 }
+0002 DUP1
+0003 ISZERO
+0004 PUSH1 0x0c
+0006 JUMPI
+0007 PUSH2 0x0102
+000a SWAP1
+000b POP
+000c JUMPDEST
+000d POP
+"#);
+}
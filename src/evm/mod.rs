@@ -0,0 +1,7 @@
+pub mod opcode;
+pub mod assembly;
+pub mod codegen;
+pub mod listing;
+pub mod source_map;
+pub mod disasm;
+pub mod process;
@@ -0,0 +1,364 @@
+//! EVM instruction table
+use std::fmt;
+
+/// Network upgrades that introduced new instructions
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Hardfork {
+  Frontier,
+  Homestead,
+  Byzantium,
+  Constantinople,
+  Istanbul,
+  London,
+  Paris,
+  Shanghai,
+  Cancun,
+}
+
+macro_rules! opcodes {
+  ($($name:ident = $byte:expr, $inputs:expr, $outputs:expr, $hardfork:ident;)*) => {
+    #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+    pub enum Opcode {
+      $($name),*
+    }
+
+    pub static OPCODES: &'static [Opcode] = &[$(Opcode::$name),*];
+
+    impl Opcode {
+      pub fn byte(&self) -> u8 {
+        match *self {
+          $(Opcode::$name => $byte),*
+        }
+      }
+
+      /// Number of stack items consumed
+      pub fn inputs(&self) -> usize {
+        match *self {
+          $(Opcode::$name => $inputs),*
+        }
+      }
+
+      /// Number of stack items produced
+      pub fn outputs(&self) -> usize {
+        match *self {
+          $(Opcode::$name => $outputs),*
+        }
+      }
+
+      pub fn mnemonic(&self) -> &'static str {
+        match *self {
+          $(Opcode::$name => stringify!($name)),*
+        }
+      }
+
+      /// Hardfork from which the instruction is available
+      pub fn hardfork(&self) -> Hardfork {
+        match *self {
+          $(Opcode::$name => Hardfork::$hardfork),*
+        }
+      }
+    }
+  }
+}
+
+opcodes! {
+  STOP = 0x00, 0, 0, Frontier;
+  ADD = 0x01, 2, 1, Frontier;
+  MUL = 0x02, 2, 1, Frontier;
+  SUB = 0x03, 2, 1, Frontier;
+  DIV = 0x04, 2, 1, Frontier;
+  SDIV = 0x05, 2, 1, Frontier;
+  MOD = 0x06, 2, 1, Frontier;
+  SMOD = 0x07, 2, 1, Frontier;
+  ADDMOD = 0x08, 3, 1, Frontier;
+  MULMOD = 0x09, 3, 1, Frontier;
+  EXP = 0x0a, 2, 1, Frontier;
+  SIGNEXTEND = 0x0b, 2, 1, Frontier;
+
+  LT = 0x10, 2, 1, Frontier;
+  GT = 0x11, 2, 1, Frontier;
+  SLT = 0x12, 2, 1, Frontier;
+  SGT = 0x13, 2, 1, Frontier;
+  EQ = 0x14, 2, 1, Frontier;
+  ISZERO = 0x15, 1, 1, Frontier;
+  AND = 0x16, 2, 1, Frontier;
+  OR = 0x17, 2, 1, Frontier;
+  XOR = 0x18, 2, 1, Frontier;
+  NOT = 0x19, 1, 1, Frontier;
+  BYTE = 0x1a, 2, 1, Frontier;
+  SHL = 0x1b, 2, 1, Constantinople;
+  SHR = 0x1c, 2, 1, Constantinople;
+  SAR = 0x1d, 2, 1, Constantinople;
+
+  KECCAK256 = 0x20, 2, 1, Frontier;
+
+  ADDRESS = 0x30, 0, 1, Frontier;
+  BALANCE = 0x31, 1, 1, Frontier;
+  ORIGIN = 0x32, 0, 1, Frontier;
+  CALLER = 0x33, 0, 1, Frontier;
+  CALLVALUE = 0x34, 0, 1, Frontier;
+  CALLDATALOAD = 0x35, 1, 1, Frontier;
+  CALLDATASIZE = 0x36, 0, 1, Frontier;
+  CALLDATACOPY = 0x37, 3, 0, Frontier;
+  CODESIZE = 0x38, 0, 1, Frontier;
+  CODECOPY = 0x39, 3, 0, Frontier;
+  GASPRICE = 0x3a, 0, 1, Frontier;
+  EXTCODESIZE = 0x3b, 1, 1, Frontier;
+  EXTCODECOPY = 0x3c, 4, 0, Frontier;
+  RETURNDATASIZE = 0x3d, 0, 1, Byzantium;
+  RETURNDATACOPY = 0x3e, 3, 0, Byzantium;
+  EXTCODEHASH = 0x3f, 1, 1, Constantinople;
+
+  BLOCKHASH = 0x40, 1, 1, Frontier;
+  COINBASE = 0x41, 0, 1, Frontier;
+  TIMESTAMP = 0x42, 0, 1, Frontier;
+  NUMBER = 0x43, 0, 1, Frontier;
+  DIFFICULTY = 0x44, 0, 1, Frontier;
+  GASLIMIT = 0x45, 0, 1, Frontier;
+  CHAINID = 0x46, 0, 1, Istanbul;
+  SELFBALANCE = 0x47, 0, 1, Istanbul;
+  BASEFEE = 0x48, 0, 1, London;
+  BLOBHASH = 0x49, 1, 1, Cancun;
+  BLOBBASEFEE = 0x4a, 0, 1, Cancun;
+
+  POP = 0x50, 1, 0, Frontier;
+  MLOAD = 0x51, 1, 1, Frontier;
+  MSTORE = 0x52, 2, 0, Frontier;
+  MSTORE8 = 0x53, 2, 0, Frontier;
+  SLOAD = 0x54, 1, 1, Frontier;
+  SSTORE = 0x55, 2, 0, Frontier;
+  JUMP = 0x56, 1, 0, Frontier;
+  JUMPI = 0x57, 2, 0, Frontier;
+  PC = 0x58, 0, 1, Frontier;
+  MSIZE = 0x59, 0, 1, Frontier;
+  GAS = 0x5a, 0, 1, Frontier;
+  JUMPDEST = 0x5b, 0, 0, Frontier;
+  TLOAD = 0x5c, 1, 1, Cancun;
+  TSTORE = 0x5d, 2, 0, Cancun;
+  MCOPY = 0x5e, 3, 0, Cancun;
+  PUSH0 = 0x5f, 0, 1, Shanghai;
+
+  PUSH1 = 0x60, 0, 1, Frontier;
+  PUSH2 = 0x61, 0, 1, Frontier;
+  PUSH3 = 0x62, 0, 1, Frontier;
+  PUSH4 = 0x63, 0, 1, Frontier;
+  PUSH5 = 0x64, 0, 1, Frontier;
+  PUSH6 = 0x65, 0, 1, Frontier;
+  PUSH7 = 0x66, 0, 1, Frontier;
+  PUSH8 = 0x67, 0, 1, Frontier;
+  PUSH9 = 0x68, 0, 1, Frontier;
+  PUSH10 = 0x69, 0, 1, Frontier;
+  PUSH11 = 0x6a, 0, 1, Frontier;
+  PUSH12 = 0x6b, 0, 1, Frontier;
+  PUSH13 = 0x6c, 0, 1, Frontier;
+  PUSH14 = 0x6d, 0, 1, Frontier;
+  PUSH15 = 0x6e, 0, 1, Frontier;
+  PUSH16 = 0x6f, 0, 1, Frontier;
+  PUSH17 = 0x70, 0, 1, Frontier;
+  PUSH18 = 0x71, 0, 1, Frontier;
+  PUSH19 = 0x72, 0, 1, Frontier;
+  PUSH20 = 0x73, 0, 1, Frontier;
+  PUSH21 = 0x74, 0, 1, Frontier;
+  PUSH22 = 0x75, 0, 1, Frontier;
+  PUSH23 = 0x76, 0, 1, Frontier;
+  PUSH24 = 0x77, 0, 1, Frontier;
+  PUSH25 = 0x78, 0, 1, Frontier;
+  PUSH26 = 0x79, 0, 1, Frontier;
+  PUSH27 = 0x7a, 0, 1, Frontier;
+  PUSH28 = 0x7b, 0, 1, Frontier;
+  PUSH29 = 0x7c, 0, 1, Frontier;
+  PUSH30 = 0x7d, 0, 1, Frontier;
+  PUSH31 = 0x7e, 0, 1, Frontier;
+  PUSH32 = 0x7f, 0, 1, Frontier;
+
+  DUP1 = 0x80, 1, 2, Frontier;
+  DUP2 = 0x81, 2, 3, Frontier;
+  DUP3 = 0x82, 3, 4, Frontier;
+  DUP4 = 0x83, 4, 5, Frontier;
+  DUP5 = 0x84, 5, 6, Frontier;
+  DUP6 = 0x85, 6, 7, Frontier;
+  DUP7 = 0x86, 7, 8, Frontier;
+  DUP8 = 0x87, 8, 9, Frontier;
+  DUP9 = 0x88, 9, 10, Frontier;
+  DUP10 = 0x89, 10, 11, Frontier;
+  DUP11 = 0x8a, 11, 12, Frontier;
+  DUP12 = 0x8b, 12, 13, Frontier;
+  DUP13 = 0x8c, 13, 14, Frontier;
+  DUP14 = 0x8d, 14, 15, Frontier;
+  DUP15 = 0x8e, 15, 16, Frontier;
+  DUP16 = 0x8f, 16, 17, Frontier;
+
+  SWAP1 = 0x90, 2, 2, Frontier;
+  SWAP2 = 0x91, 3, 3, Frontier;
+  SWAP3 = 0x92, 4, 4, Frontier;
+  SWAP4 = 0x93, 5, 5, Frontier;
+  SWAP5 = 0x94, 6, 6, Frontier;
+  SWAP6 = 0x95, 7, 7, Frontier;
+  SWAP7 = 0x96, 8, 8, Frontier;
+  SWAP8 = 0x97, 9, 9, Frontier;
+  SWAP9 = 0x98, 10, 10, Frontier;
+  SWAP10 = 0x99, 11, 11, Frontier;
+  SWAP11 = 0x9a, 12, 12, Frontier;
+  SWAP12 = 0x9b, 13, 13, Frontier;
+  SWAP13 = 0x9c, 14, 14, Frontier;
+  SWAP14 = 0x9d, 15, 15, Frontier;
+  SWAP15 = 0x9e, 16, 16, Frontier;
+  SWAP16 = 0x9f, 17, 17, Frontier;
+
+  LOG0 = 0xa0, 2, 0, Frontier;
+  LOG1 = 0xa1, 3, 0, Frontier;
+  LOG2 = 0xa2, 4, 0, Frontier;
+  LOG3 = 0xa3, 5, 0, Frontier;
+  LOG4 = 0xa4, 6, 0, Frontier;
+
+  CREATE = 0xf0, 3, 1, Frontier;
+  CALL = 0xf1, 7, 1, Frontier;
+  CALLCODE = 0xf2, 7, 1, Frontier;
+  RETURN = 0xf3, 2, 0, Frontier;
+  DELEGATECALL = 0xf4, 6, 1, Homestead;
+  CREATE2 = 0xf5, 4, 1, Constantinople;
+  STATICCALL = 0xfa, 6, 1, Byzantium;
+  REVERT = 0xfd, 2, 0, Byzantium;
+  INVALID = 0xfe, 0, 0, Frontier;
+  SELFDESTRUCT = 0xff, 1, 0, Frontier;
+}
+
+/// Other names instructions go by, with the hardfork introducing each
+static ALIASES: &'static [(&'static str, Opcode, Hardfork)] = &[
+  ("sha3", Opcode::KECCAK256, Hardfork::Frontier),
+  ("prevrandao", Opcode::DIFFICULTY, Hardfork::Paris),
+  ("suicide", Opcode::SELFDESTRUCT, Hardfork::Frontier),
+];
+
+impl Opcode {
+  pub fn from_byte(byte: u8) -> Option<Opcode> {
+    OPCODES.iter().find(|op| op.byte() == byte).cloned()
+  }
+
+  /// Looks up an opcode by its lowercase assembly name, e.g. `mstore`
+  pub fn from_name(name: &str) -> Option<Opcode> {
+    Opcode::lookup(name).map(|(op, _)| op)
+  }
+
+  /// Hardfork from which `name` refers to an instruction, which for an alias
+  /// may be later than the instruction itself
+  pub fn name_hardfork(name: &str) -> Option<Hardfork> {
+    Opcode::lookup(name).map(|(_, hardfork)| hardfork)
+  }
+
+  fn lookup(name: &str) -> Option<(Opcode, Hardfork)> {
+    if name.bytes().any(|b| b.is_ascii_uppercase()) {
+      return None;
+    }
+
+    if let Some(&(_, op, hardfork)) = ALIASES.iter().find(|&&(alias, _, _)| alias == name) {
+      return Some((op, hardfork));
+    }
+
+    OPCODES.iter()
+      .find(|op| op.mnemonic().eq_ignore_ascii_case(name))
+      .map(|&op| (op, op.hardfork()))
+  }
+
+  /// Looks up an opcode that may be invoked in functional style, i.e. any
+  /// instruction other than the stack manipulation ones
+  pub fn builtin(name: &str) -> Option<Opcode> {
+    match Opcode::from_name(name) {
+      Some(op) if op.is_push().is_none() && op.is_dup().is_none() && op.is_swap().is_none() &&
+                  op != Opcode::PUSH0 && op != Opcode::JUMPDEST => Some(op),
+      _ => None,
+    }
+  }
+
+  /// PUSHn for `n` bytes of immediate data (1 to 32)
+  pub fn push(n: usize) -> Opcode {
+    assert!(n >= 1 && n <= 32);
+    Opcode::from_byte(0x5f + n as u8).unwrap()
+  }
+
+  /// DUPn for the `n`th stack item (1 to 16)
+  pub fn dup(n: usize) -> Opcode {
+    assert!(n >= 1 && n <= 16);
+    Opcode::from_byte(0x7f + n as u8).unwrap()
+  }
+
+  /// SWAPn for the `n+1`th stack item (1 to 16)
+  pub fn swap(n: usize) -> Opcode {
+    assert!(n >= 1 && n <= 16);
+    Opcode::from_byte(0x8f + n as u8).unwrap()
+  }
+
+  /// Number of immediate data bytes following a PUSH instruction
+  pub fn is_push(&self) -> Option<usize> {
+    let byte = self.byte();
+    if byte >= 0x60 && byte <= 0x7f {
+      Some((byte - 0x5f) as usize)
+    } else {
+      None
+    }
+  }
+
+  pub fn is_dup(&self) -> Option<usize> {
+    let byte = self.byte();
+    if byte >= 0x80 && byte <= 0x8f {
+      Some((byte - 0x7f) as usize)
+    } else {
+      None
+    }
+  }
+
+  pub fn is_swap(&self) -> Option<usize> {
+    let byte = self.byte();
+    if byte >= 0x90 && byte <= 0x9f {
+      Some((byte - 0x8f) as usize)
+    } else {
+      None
+    }
+  }
+}
+
+impl fmt::Display for Opcode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.mnemonic())
+  }
+}
+
+#[test]
+fn it_looks_up_opcodes_by_name_and_byte() {
+  assert_eq!(Opcode::from_name("mstore"), Some(Opcode::MSTORE));
+  assert_eq!(Opcode::from_name("sha3"), Some(Opcode::KECCAK256));
+  assert_eq!(Opcode::from_name("prevrandao"), Some(Opcode::DIFFICULTY));
+  assert_eq!(Opcode::from_name("MSTORE"), None);
+  assert_eq!(Opcode::from_byte(0x52), Some(Opcode::MSTORE));
+  assert_eq!(Opcode::from_byte(0x0c), None);
+
+  assert_eq!(Opcode::push(32), Opcode::PUSH32);
+  assert_eq!(Opcode::dup(16), Opcode::DUP16);
+  assert_eq!(Opcode::swap(1), Opcode::SWAP1);
+}
+
+#[test]
+fn it_records_the_hardfork_introducing_each_opcode() {
+  assert_eq!(Opcode::ADD.hardfork(), Hardfork::Frontier);
+  assert_eq!(Opcode::DELEGATECALL.hardfork(), Hardfork::Homestead);
+  assert_eq!(Opcode::SHL.hardfork(), Hardfork::Constantinople);
+  assert_eq!(Opcode::PUSH0.hardfork(), Hardfork::Shanghai);
+  assert_eq!(Opcode::MCOPY.hardfork(), Hardfork::Cancun);
+  assert!(Hardfork::Byzantium < Hardfork::Istanbul);
+
+  assert_eq!(Opcode::DIFFICULTY.hardfork(), Hardfork::Frontier);
+  assert_eq!(Opcode::name_hardfork("difficulty"), Some(Hardfork::Frontier));
+  assert_eq!(Opcode::name_hardfork("prevrandao"), Some(Hardfork::Paris));
+  assert_eq!(Opcode::name_hardfork("push0"), Some(Hardfork::Shanghai));
+}
+
+#[test]
+fn it_does_not_expose_stack_instructions_as_builtins() {
+  assert_eq!(Opcode::builtin("add"), Some(Opcode::ADD));
+  assert_eq!(Opcode::builtin("jump"), Some(Opcode::JUMP));
+  assert_eq!(Opcode::builtin("push0"), None);
+  assert_eq!(Opcode::builtin("push1"), None);
+  assert_eq!(Opcode::builtin("dup1"), None);
+  assert_eq!(Opcode::builtin("swap1"), None);
+  assert_eq!(Opcode::builtin("jumpdest"), None);
+}
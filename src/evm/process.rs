@@ -0,0 +1,302 @@
+use std::io::{self, Write};
+use process::{Processor, ProcessResult};
+use process::state::{ProcessState, ErrorState, Done, HasConfig, HasSource, HasAST, HasBytecode};
+use process::diagnostic::Diagnostic;
+use config::{Config, Target, BytecodeFormat};
+use asm::ast::{Node, Block, Span};
+use evm::assembly::{Assembly, Placed};
+use evm::codegen::{self, CodeGenerator};
+use evm::listing::ListingPrinter;
+use evm::source_map::SourceMap;
+use evm::disasm::{Disassembly, DisassemblyPrinter};
+
+extern crate rustc_serialize;
+use self::rustc_serialize::hex::{ToHex, FromHex, FromHexError};
+
+
+// Assembled
+//
+#[derive(Debug, Clone)]
+pub struct Assembled {
+  config: Config,
+  source: String,
+  ast: Node<Block>,
+  instructions: Vec<Placed>,
+}
+
+impl Assembled {
+  pub fn new(instructions: Vec<Placed>,
+             ast: Node<Block>,
+             source: String,
+             config: Config)
+             -> Assembled {
+    Assembled {
+      config: config,
+      source: source,
+      ast: ast,
+      instructions: instructions,
+    }
+  }
+}
+
+impl ProcessState for Assembled {}
+
+impl HasConfig for Assembled {
+  fn get_config(self) -> Config {
+    self.config
+  }
+}
+
+impl HasSource for Assembled {
+  fn get_source(self) -> String {
+    self.source
+  }
+}
+
+impl HasAST for Assembled {
+  fn get_ast(self) -> Node<Block> {
+    self.ast
+  }
+}
+
+impl HasBytecode for Assembled {
+  fn get_bytecode(self) -> Vec<u8> {
+    Assembly::encode(&self.instructions)
+  }
+}
+
+
+// CodegenError
+//
+#[derive(Debug, Clone)]
+pub struct CodegenError {
+  error: codegen::CodegenError,
+  span: Span,
+  source: String,
+  config: Config,
+}
+
+impl CodegenError {
+  pub fn new(error: codegen::CodegenError,
+             span: Span,
+             source: String,
+             config: Config)
+             -> CodegenError {
+    CodegenError {
+      error: error,
+      span: span,
+      source: source,
+      config: config,
+    }
+  }
+
+  pub fn diagnostic(&self) -> Diagnostic {
+    Diagnostic::new("CodegenError", self.error.to_string(), self.span)
+  }
+}
+
+impl ProcessState for CodegenError {}
+
+impl ErrorState for CodegenError {
+  fn write<W: Write>(self, out: &mut W) {
+    self.diagnostic().write(out, &self.source, &self.config);
+  }
+}
+
+
+// Disassembled
+//
+#[derive(Debug, Clone)]
+pub struct Disassembled {
+  config: Config,
+  disassembly: Disassembly,
+}
+
+impl Disassembled {
+  pub fn new(disassembly: Disassembly, config: Config) -> Disassembled {
+    Disassembled {
+      config: config,
+      disassembly: disassembly,
+    }
+  }
+}
+
+impl ProcessState for Disassembled {}
+
+impl HasConfig for Disassembled {
+  fn get_config(self) -> Config {
+    self.config
+  }
+}
+
+
+// HexError
+//
+#[derive(Debug, Clone)]
+pub struct HexError {
+  message: String,
+  span: Span,
+  source: String,
+  config: Config,
+}
+
+impl HexError {
+  pub fn new(message: String, span: Span, source: String, config: Config) -> HexError {
+    HexError {
+      message: message,
+      span: span,
+      source: source,
+      config: config,
+    }
+  }
+
+  pub fn diagnostic(&self) -> Diagnostic {
+    Diagnostic::new("HexError", self.message.clone(), self.span)
+  }
+}
+
+impl ProcessState for HexError {}
+
+impl ErrorState for HexError {
+  fn write<W: Write>(self, out: &mut W) {
+    self.diagnostic().write(out, &self.source, &self.config);
+  }
+}
+
+
+impl<S: HasConfig> Processor<S> {
+  pub fn disassemble<'a>(self) -> ProcessResult<Disassembled, HexError> {
+    let config = self.clone().config();
+    let source = self.read(config.clone());
+
+    // allow surrounding whitespace and a 0x prefix, keeping track of where
+    // the digits start to point errors at the input
+    let mut start = source.find(|c: char| !c.is_whitespace()).unwrap_or(0);
+    let mut hex = source.trim();
+    if hex.starts_with("0x") {
+      start += 2;
+      hex = &hex[2..];
+    }
+
+    match hex.from_hex() {
+      Ok(bytes) => {
+        let disassembly = Disassembly::decode(&bytes);
+        Ok(Processor { state: Disassembled::new(disassembly, config) })
+      }
+      Err(err) => {
+        let (message, span) = match err {
+          FromHexError::InvalidHexCharacter(c, index) => {
+            (format!("invalid hex character {:?}", c),
+             Span::new(start + index, start + index + c.len_utf8()))
+          }
+          FromHexError::InvalidHexLength => {
+            (String::from("odd number of hex digits"),
+             Span::new(start + hex.len(), start + hex.len()))
+          }
+        };
+
+        Err(Processor { state: HexError::new(message, span, source.clone(), config) })
+      }
+    }
+  }
+}
+
+impl Processor<Disassembled> {
+  pub fn emit_disassembly<'a, E: ErrorState>(self) -> ProcessResult<Disassembled, E> {
+    let config = self.clone().config();
+
+    if config.targets(Target::Disassembly) {
+      let disassembly = self.clone().disassembly();
+      let mut out = io::stdout();
+      DisassemblyPrinter::print(&disassembly, &mut out);
+    }
+
+    Ok(self)
+  }
+
+  pub fn done<'a, E: ErrorState>(self) -> ProcessResult<Done, E> {
+    Ok(Processor { state: Done::new() })
+  }
+
+  pub fn disassembly(self) -> Disassembly {
+    self.state.disassembly
+  }
+}
+
+impl<S: HasAST> Processor<S> {
+  pub fn assemble<'a>(self) -> ProcessResult<Assembled, CodegenError> {
+    let config = self.clone().config();
+    let source = self.clone().source();
+    let ast = self.ast();
+    let result = CodeGenerator::generate(&ast);
+
+    let result = result.and_then(|assembly| {
+      assembly.place().map_err(|err| {
+        (err.span().unwrap_or(ast.span), codegen::CodegenError::Assembly(err))
+      })
+    });
+
+    match result {
+      Ok(instructions) => {
+        Ok(Processor { state: Assembled::new(instructions, ast, source, config) })
+      }
+      Err((span, err)) => Err(Processor { state: CodegenError::new(err, span, source, config) }),
+    }
+  }
+}
+
+impl<S: HasBytecode> Processor<S> {
+  pub fn emit<'a, E: ErrorState>(self) -> ProcessResult<S, E> {
+    let config = self.clone().config();
+
+    if config.clone().targets(Target::Bytecode) {
+      let bytecode = self.clone().bytecode();
+      let mut out = io::stdout();
+
+      match config.bytecode_format {
+        BytecodeFormat::Hex => {
+          write!(&mut out, "{}\n", bytecode.to_hex()).ok();
+        }
+        BytecodeFormat::Raw => {
+          out.write_all(&bytecode).ok();
+        }
+      }
+    }
+
+    Ok(self)
+  }
+
+  pub fn bytecode(self) -> Vec<u8> {
+    self.state.get_bytecode()
+  }
+}
+
+impl Processor<Assembled> {
+  pub fn emit_opcodes<'a, E: ErrorState>(self) -> ProcessResult<Assembled, E> {
+    let config = self.clone().config();
+
+    if config.targets(Target::Opcodes) {
+      let source = self.clone().source();
+      let mut out = io::stdout();
+      ListingPrinter::print(&self.state.instructions, &source, &mut out);
+    }
+
+    Ok(self)
+  }
+
+  pub fn emit_source_map<'a, E: ErrorState>(self) -> ProcessResult<Assembled, E> {
+    let config = self.clone().config();
+
+    if config.targets(Target::SourceMap) {
+      let source_map = self.clone().source_map();
+      let mut out = io::stdout();
+      write!(&mut out, "{}\n", source_map).ok();
+    }
+
+    Ok(self)
+  }
+
+  pub fn source_map(self) -> SourceMap {
+    SourceMap::new(&self.state.instructions)
+  }
+}
@@ -0,0 +1,117 @@
+//! Source maps in solc's compressed format, relating each instruction to the
+//! source it was generated from
+//!
+//! Each instruction gets an entry `s:l:f:j`, separated by `;`: the start
+//! offset and length of its source, the index of the source file (always 0
+//! here) and whether it jumps into (`i`) or out of (`o`) a function, or
+//! neither (`-`). Instructions the compiler adds without any source behind
+//! them are mapped to `-1:-1:-1`. Fields that are the same as in the entry
+//! before are left empty, and trailing empty fields are dropped along with
+//! their colons.
+use std::fmt;
+
+use asm::ast::Span;
+use evm::assembly::{JumpType, Placed};
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Entry {
+  pub span: Option<Span>,
+  pub jump: JumpType,
+}
+
+impl Entry {
+  fn fields(&self) -> [String; 4] {
+    let jump = match self.jump {
+      JumpType::Regular => "-",
+      JumpType::IntoFunction => "i",
+      JumpType::OutOfFunction => "o",
+    };
+
+    match self.span {
+      Some(span) => {
+        [span.start.to_string(),
+         (span.end - span.start).to_string(),
+         "0".to_string(),
+         jump.to_string()]
+      }
+      None => ["-1".to_string(), "-1".to_string(), "-1".to_string(), jump.to_string()],
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct SourceMap {
+  pub entries: Vec<Entry>,
+}
+
+impl SourceMap {
+  pub fn new(instructions: &[Placed]) -> SourceMap {
+    SourceMap {
+      entries: instructions.iter()
+        .map(|instruction| {
+          Entry {
+            span: instruction.span,
+            jump: instruction.jump,
+          }
+        })
+        .collect(),
+    }
+  }
+}
+
+impl fmt::Display for SourceMap {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut previous: Option<[String; 4]> = None;
+    let mut compressed = vec![];
+
+    for entry in &self.entries {
+      let fields = entry.fields();
+      let mut changed: Vec<&str> = fields.iter()
+        .enumerate()
+        .map(|(i, field)| match previous {
+          Some(ref previous) if previous[i] == *field => "",
+          _ => field.as_str(),
+        })
+        .collect();
+      while changed.last() == Some(&"") {
+        changed.pop();
+      }
+
+      compressed.push(changed.join(":"));
+      previous = Some(fields);
+    }
+
+    write!(f, "{}", compressed.join(";"))
+  }
+}
+
+#[cfg(test)]
+use asm::grammar;
+
+#[cfg(test)]
+use evm::codegen::CodeGenerator;
+
+#[cfg(test)]
+fn source_map(program: &str) -> String {
+  let block = grammar::block(program).unwrap();
+  let instructions = CodeGenerator::generate(&block).unwrap().place().unwrap();
+  SourceMap::new(&instructions).to_string()
+}
+
+#[test]
+fn it_compresses_repeated_fields() {
+  // PUSH1 0x60, PUSH1 0x40, MSTORE
+  assert_eq!(source_map("{ mstore(0x40, 0x60) }"), "15:4:0:-;9;2:18");
+}
+
+#[test]
+fn it_marks_jumps_into_and_out_of_functions() {
+  let map = source_map("{ function f() { } f() }");
+  let jumps: Vec<&str> = map.split(';')
+    .filter(|e| e.ends_with(":i") || e.ends_with(":o"))
+    .collect();
+
+  assert_eq!(jumps.len(), 2);
+  assert!(jumps[0].ends_with(":o"));
+  assert!(jumps[1].ends_with(":i"));
+}
@@ -5,7 +5,7 @@ extern crate clap;
 
 extern crate solasm;
 use solasm::process::Processor;
-use solasm::config::{Config, Target};
+use solasm::config::{Config, Target, BytecodeFormat, ErrorFormat};
 use solasm::process::plan::{self, Plan};
 
 fn main() {
@@ -14,7 +14,19 @@ fn main() {
     (author: "g. nicholas d'andrea <nick@gnidan.org>")
     (about: "EVM Assembly Language compiler")
     (@arg ast: --ast "Output formatted assembly")
+    (@arg ast_json: --("ast-json") "Output the AST as JSON")
+    (@arg desugared: --desugared "Output assembly lowered to labels and jumps")
+    (@arg bin: --bin "Output bytecode as hex")
+    (@arg opcodes: --opcodes "Output the instructions with their offsets")
+    (@arg source_map: --("source-map") "Output the source map of the bytecode")
+    (@arg raw: --raw "Output bytecode as raw bytes")
     (@arg filename: -f --filename[FILE] "Read from file instead of stdin")
+    (@arg error_format: --("error-format")[FORMAT] possible_value[human json]
+      "Format of error messages")
+    (@subcommand disasm =>
+      (about: "Disassemble hex bytecode into instructions")
+      (@arg filename: -f --filename[FILE] "Read from file instead of stdin")
+    )
   )
       .get_matches();
 
@@ -24,14 +36,61 @@ fn main() {
     config.target(Target::Assembly);
   }
 
+  if args.is_present("ast_json") {
+    config.target(Target::AstJson);
+  }
+
+  if args.is_present("desugared") {
+    config.target(Target::Desugared);
+  }
+
+  if args.is_present("bin") {
+    config.target(Target::Bytecode);
+  }
+
+  if args.is_present("opcodes") {
+    config.target(Target::Opcodes);
+  }
+
+  if args.is_present("source_map") {
+    config.target(Target::SourceMap);
+  }
+
+  if args.is_present("raw") {
+    config.target(Target::Bytecode);
+    config.bytecode_format(BytecodeFormat::Raw);
+  }
+
   if args.is_present("filename") {
     config.source_file(args.value_of("filename").unwrap());
   }
 
 
-  let result = Processor::new()
-    .configure(config.clone())
-    .and_then(plan::FormatAssembly::run);
+  if args.value_of("error_format") == Some("json") {
+    config.error_format(ErrorFormat::Json);
+  }
+
+  if let Some(disasm) = args.subcommand_matches("disasm") {
+    config.target(Target::Disassembly);
+
+    if disasm.is_present("filename") {
+      config.source_file(disasm.value_of("filename").unwrap());
+    }
+  }
+
+  let processor = Processor::new().configure(config.clone());
+  let compiles = config.clone().targets(Target::Bytecode) ||
+                 config.clone().targets(Target::Opcodes) ||
+                 config.clone().targets(Target::SourceMap);
+  let result = if config.clone().targets(Target::Disassembly) {
+    processor.and_then(plan::Disassemble::run)
+  } else if config.clone().targets(Target::Desugared) {
+    processor.and_then(plan::Desugar::run)
+  } else if compiles {
+    processor.and_then(plan::Compile::run)
+  } else {
+    processor.and_then(plan::FormatAssembly::run)
+  };
 
   match result {
     Ok(_) => exit(0),
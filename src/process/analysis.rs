@@ -0,0 +1,118 @@
+//! Semantic checks over the parsed AST
+use std::io::Write;
+use process::{Processor, ProcessResult};
+use process::state::{ProcessState, ErrorState, HasConfig, HasSource, HasAST};
+use process::diagnostic::Diagnostic;
+use process::symbols::{ScopeVisitor, SymbolTable, SemanticError};
+use process::validation::ControlOpVisitor;
+use config::Config;
+use asm::ast::{Node, Block, Span};
+
+
+// Analyzed
+//
+#[derive(Debug, Clone)]
+pub struct Analyzed {
+  config: Config,
+  source: String,
+  ast: Node<Block>,
+  symbols: SymbolTable,
+}
+
+impl Analyzed {
+  pub fn new(symbols: SymbolTable, ast: Node<Block>, source: String, config: Config) -> Analyzed {
+    Analyzed {
+      config: config,
+      source: source,
+      ast: ast,
+      symbols: symbols,
+    }
+  }
+}
+
+impl ProcessState for Analyzed {}
+
+impl HasConfig for Analyzed {
+  fn get_config(self) -> Config {
+    self.config
+  }
+}
+
+impl HasSource for Analyzed {
+  fn get_source(self) -> String {
+    self.source
+  }
+}
+
+impl HasAST for Analyzed {
+  fn get_ast(self) -> Node<Block> {
+    self.ast
+  }
+}
+
+
+// AnalysisError
+//
+#[derive(Debug, Clone)]
+pub struct AnalysisError {
+  errors: Vec<(Span, SemanticError)>,
+  source: String,
+  config: Config,
+}
+
+impl AnalysisError {
+  pub fn new(errors: Vec<(Span, SemanticError)>, source: String, config: Config) -> AnalysisError {
+    AnalysisError {
+      errors: errors,
+      source: source,
+      config: config,
+    }
+  }
+
+  pub fn diagnostics(&self) -> Vec<Diagnostic> {
+    self.errors
+      .iter()
+      .map(|&(span, ref error)| Diagnostic::new("AnalysisError", error.to_string(), span))
+      .collect()
+  }
+}
+
+impl ProcessState for AnalysisError {}
+
+impl ErrorState for AnalysisError {
+  fn write<W: Write>(self, out: &mut W) {
+    for diagnostic in self.diagnostics() {
+      diagnostic.write(out, &self.source, &self.config);
+    }
+  }
+}
+
+
+impl<S: HasAST> Processor<S> {
+  pub fn analyze<'a>(self) -> ProcessResult<Analyzed, AnalysisError> {
+    let config = self.clone().config();
+    let source = self.clone().source();
+    let ast = self.ast();
+
+    let (symbols, mut errors) = {
+      let mut visitor = ScopeVisitor::new(&ast);
+      let symbols = visitor.visit().clone();
+      (symbols, visitor.errors().clone())
+    };
+
+    errors.extend(ControlOpVisitor::new(&ast).visit().iter().cloned());
+    errors.sort_by_key(|&(span, _)| span.start);
+
+    if errors.is_empty() {
+      Ok(Processor { state: Analyzed::new(symbols, ast, source, config) })
+    } else {
+      Err(Processor { state: AnalysisError::new(errors, source, config) })
+    }
+  }
+}
+
+impl Processor<Analyzed> {
+  pub fn symbols(self) -> SymbolTable {
+    self.state.symbols
+  }
+}
@@ -0,0 +1,122 @@
+//! Rendering of errors that point into the source
+use std::collections::BTreeMap;
+use std::io::Write;
+
+extern crate rustc_serialize;
+use self::rustc_serialize::json::Json;
+
+use config::{Config, ErrorFormat};
+use asm::ast::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+  pub kind: &'static str,
+  pub message: String,
+  pub span: Span,
+  pub expected: Vec<String>,
+}
+
+impl Diagnostic {
+  pub fn new(kind: &'static str, message: String, span: Span) -> Diagnostic {
+    Diagnostic {
+      kind: kind,
+      message: message,
+      span: span,
+      expected: vec![],
+    }
+  }
+
+  pub fn expected(mut self, expected: Vec<String>) -> Diagnostic {
+    self.expected = expected;
+    self
+  }
+
+  pub fn write<W: Write>(&self, out: &mut W, source: &str, config: &Config) {
+    match config.error_format {
+      ErrorFormat::Human => self.write_human(out, source, config),
+      ErrorFormat::Json => self.write_json(out, source, config),
+    }
+  }
+
+  /// Writes the message followed by the offending line, with a caret under
+  /// the start of the span
+  ///
+  /// ```text
+  /// ParseError: expected one of ")", ","
+  ///   --> test.asm:2:17
+  ///   |
+  /// 2 |   let x := add(1
+  ///   |                 ^
+  /// ```
+  fn write_human<W: Write>(&self, out: &mut W, source: &str, config: &Config) {
+    let position = self.span.position(source);
+    let line = source.lines().nth(position.line - 1).unwrap_or("");
+    let number = position.line.to_string();
+    let gutter: String = number.chars().map(|_| ' ').collect();
+
+    // keep tabs so the caret lines up with the source line
+    let indent: String = line.chars()
+      .take(position.column - 1)
+      .map(|c| if c == '\t' { '\t' } else { ' ' })
+      .collect();
+
+    write!(out, "{}: {}\n", self.kind, self.message).ok();
+    write!(out, "{} --> {}:{}\n", gutter, config.source.name(), position).ok();
+    write!(out, "{} |\n", gutter).ok();
+    write!(out, "{} | {}\n", number, line).ok();
+    write!(out, "{} | {}^\n", gutter, indent).ok();
+  }
+
+  fn write_json<W: Write>(&self, out: &mut W, source: &str, config: &Config) {
+    let position = self.span.position(source);
+
+    let mut object = BTreeMap::new();
+    object.insert("type".to_string(), Json::String(self.kind.to_string()));
+    object.insert("message".to_string(), Json::String(self.message.clone()));
+    object.insert("file".to_string(), Json::String(config.source.name()));
+    object.insert("line".to_string(), Json::U64(position.line as u64));
+    object.insert("column".to_string(), Json::U64(position.column as u64));
+    object.insert("start".to_string(), Json::U64(self.span.start as u64));
+    object.insert("end".to_string(), Json::U64(self.span.end as u64));
+    object.insert("expected".to_string(),
+                  Json::Array(self.expected.iter().map(|e| Json::String(e.clone())).collect()));
+
+    write!(out, "{}\n", Json::Object(object)).ok();
+  }
+}
+
+#[cfg(test)]
+use std::str::from_utf8;
+
+#[cfg(test)]
+fn render(diagnostic: &Diagnostic, source: &str, config: &Config) -> String {
+  let mut buf = vec![];
+  diagnostic.write(&mut buf, source, config);
+  from_utf8(&buf).unwrap().to_string()
+}
+
+#[test]
+fn it_renders_the_offending_line_with_a_caret() {
+  let mut config = Config::new();
+  config.source_file("test.asm");
+
+  let source = "{\n\tlet x = 1\n}";
+  let diagnostic = Diagnostic::new("ParseError", "expected \":=\"".to_string(), Span::new(9, 9));
+
+  assert_eq!(render(&diagnostic, source, &config),
+             "ParseError: expected \":=\"\n  --> test.asm:2:8\n  |\n2 | \tlet x = 1\n  | \t      ^\n");
+}
+
+#[test]
+fn it_renders_json() {
+  let mut config = Config::new();
+  config.error_format(ErrorFormat::Json);
+
+  let source = "{ ! }";
+  let diagnostic = Diagnostic::new("ParseError", "expected \"}\"".to_string(), Span::new(2, 2))
+    .expected(vec!["\"}\"".to_string()]);
+
+  assert_eq!(render(&diagnostic, source, &config),
+             "{\"column\":3,\"end\":2,\"expected\":[\"\\\"}\\\"\"],\"file\":\"<stdin>\",\"line\":1,\
+              \"message\":\"expected \\\"}\\\"\",\"start\":2,\"type\":\"ParseError\"}\n");
+}
@@ -1,6 +1,9 @@
 pub mod state;
 pub mod plan;
 pub mod symbols;
+pub mod analysis;
+pub mod validation;
+pub mod diagnostic;
 
 mod process;
 pub use self::process::*;
@@ -21,8 +21,47 @@ impl<S: HasConfig> Plan<S, Done, Error> for FormatAssembly {
   }
 }
 
+pub struct Compile {}
+
+impl<S: HasConfig> Plan<S, Done, Error> for Compile {
+  fn run(processor: Processor<S>) -> ProcessResult<Done, Error> {
+    processor.parse()
+      .or_else(|p| p.err())
+      .and_then(|p| p.analyze().or_else(|p| p.err()))
+      .and_then(|p| p.assemble().or_else(|p| p.err()))
+      .and_then(|p| p.emit())
+      .and_then(|p| p.emit_opcodes())
+      .and_then(|p| p.emit_source_map())
+      .and_then(|p| p.target())
+  }
+}
+
+pub struct Desugar {}
+
+impl<S: HasConfig> Plan<S, Done, Error> for Desugar {
+  fn run(processor: Processor<S>) -> ProcessResult<Done, Error> {
+    processor.parse()
+      .or_else(|p| p.err())
+      .and_then(|p| p.analyze().or_else(|p| p.err()))
+      .and_then(|p| p.desugar())
+      .and_then(|p| p.emit_desugared())
+      .and_then(|p| p.target())
+  }
+}
+
+pub struct Disassemble {}
+
+impl<S: HasConfig> Plan<S, Done, Error> for Disassemble {
+  fn run(processor: Processor<S>) -> ProcessResult<Done, Error> {
+    processor.disassemble()
+      .or_else(|p| p.err())
+      .and_then(|p| p.emit_disassembly())
+      .and_then(|p| p.done())
+  }
+}
+
 #[cfg(test)]
-use config::Config;
+use config::{Config, Target};
 
 #[test]
 fn it_parses_correctly() {
@@ -47,3 +86,80 @@ fn it_errors_correctly() {
 
   assert!(result.is_err());
 }
+
+#[test]
+fn it_compiles_correctly() {
+  let mut config = Config::new();
+  config.source_str("{ mstore(0x40, 0x60) }");
+
+  let result = Processor::new()
+    .configure(config.clone())
+    .and_then(Compile::run);
+
+  assert!(result.is_ok());
+}
+
+#[test]
+fn it_desugars_correctly() {
+  let mut config = Config::new();
+  config.source_str("{ function f() -> r { r := 1 } for { } f() { } { break } }");
+  config.target(Target::Desugared);
+
+  let result = Processor::new()
+    .configure(config.clone())
+    .and_then(Desugar::run);
+
+  assert!(result.is_ok());
+
+  config.source_str("{ function f() {} f(1) }");
+
+  let result = Processor::new()
+    .configure(config.clone())
+    .and_then(Desugar::run);
+
+  assert!(result.is_err());
+}
+
+#[test]
+fn it_errors_on_analysis_failure() {
+  let mut config = Config::new();
+  config.source_str("{ frobinate(0x40) }");
+
+  let result = Processor::new()
+    .configure(config.clone())
+    .and_then(Compile::run);
+
+  assert!(result.is_err());
+}
+
+#[test]
+fn it_errors_on_codegen_failure() {
+  let mut config = Config::new();
+  config.source_str("{ assembly frobinate { } }");
+
+  let result = Processor::new()
+    .configure(config.clone())
+    .and_then(Compile::run);
+
+  assert!(result.is_err());
+}
+
+#[test]
+fn it_disassembles_correctly() {
+  let mut config = Config::new();
+  config.source_str("0x6004560c5b\n");
+
+  let result = Processor::new()
+    .configure(config.clone())
+    .and_then(Disassemble::run);
+
+  assert!(result.is_ok());
+
+  config.source_str("6004560g5b");
+
+  let result = Processor::new()
+    .configure(config.clone())
+    .and_then(Disassemble::run);
+
+  assert!(result.is_err());
+}
@@ -25,8 +25,15 @@ impl<S: HasAST> Processor<S> {
     let ast = self.clone().ast();
 
     if config.clone().targets(Target::Assembly) {
+      let source = self.clone().source();
+      let trivia = asm::trivia::Trivia::collect(&ast, &source);
       let mut out: BufWriter<_> = BufWriter::new(io::stdout());
-      asm::pretty::PrettyPrinter::print(&ast, &mut out);
+      asm::pretty::PrettyPrinter::print_with_trivia(&ast, &trivia, &mut out);
+    }
+
+    if config.clone().targets(Target::AstJson) {
+      let mut out: BufWriter<_> = BufWriter::new(io::stdout());
+      asm::json::JsonPrinter::print(&ast, &mut out);
     }
 
     Ok(Processor { state: Done {} })
@@ -38,7 +45,7 @@ impl<S: HasAST> Processor<S> {
 }
 
 impl<E: ErrorState> Processor<E> {
-  pub fn err<'a>(self) -> ProcessResult<Done, Error> {
+  pub fn err<'a, T: ProcessState>(self) -> ProcessResult<T, Error> {
     let mut out: BufWriter<_> = BufWriter::new(io::stderr());
     self.state.write(&mut out);
     Err(Processor { state: Error {} })
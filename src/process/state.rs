@@ -22,10 +22,14 @@ pub trait HasSource: ProcessState {
   fn get_source(self) -> String;
 }
 
-pub trait HasAST: HasConfig {
+pub trait HasAST: HasConfig + HasSource {
   fn get_ast(self) -> Node<Block>;
 }
 
+pub trait HasBytecode: HasAST {
+  fn get_bytecode(self) -> Vec<u8>;
+}
+
 // New
 //
 #[derive(Debug, Clone, Default)]
@@ -1,10 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::Entry;
-use std::cell::Cell;
+use std::fmt;
 
 use asm::ast;
 use asm::ast::visitor::Visitor;
 use asm::ast::visitor::Walkable;
+use evm::opcode::Opcode;
 
 type Sid = u64;
 
@@ -18,17 +19,62 @@ pub enum SymbolTableError {
 #[derive(Debug, Clone)]
 struct Scope {
   parent_sid: Option<Sid>,
-  variables: HashMap<ast::Identifier, Variable>,
+  variables: HashMap<String, Variable>,
+  functions: HashMap<String, Function>,
+  labels: HashSet<String>,
+  /// Variables and labels of enclosing scopes are not visible past a function body
+  boundary: bool,
 }
 
+impl Scope {
+  fn new(parent_sid: Option<Sid>, boundary: bool) -> Scope {
+    Scope {
+      parent_sid: parent_sid,
+      variables: HashMap::new(),
+      functions: HashMap::new(),
+      labels: HashSet::new(),
+      boundary: boundary,
+    }
+  }
+}
+
+/// A variable, along with its initial value unless it is a function
+/// argument or return variable
 #[derive(Debug, Clone, PartialEq)]
 pub struct Variable {
-  expression: ast::Expression,
+  expression: Option<ast::Expression>,
+  pub type_name: Option<String>,
 }
 
 impl Variable {
-  pub fn new(expression: ast::Expression) -> Variable {
-    Variable { expression: expression }
+  pub fn new(expression: ast::Expression, type_name: Option<String>) -> Variable {
+    Variable {
+      expression: Some(expression),
+      type_name: type_name,
+    }
+  }
+
+  pub fn parameter(type_name: Option<String>) -> Variable {
+    Variable {
+      expression: None,
+      type_name: type_name,
+    }
+  }
+}
+
+/// A function, with the types of its arguments and return values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+  pub arguments: Vec<Option<String>>,
+  pub returns: Vec<Option<String>>,
+}
+
+impl Function {
+  pub fn new(arguments: Vec<Option<String>>, returns: Vec<Option<String>>) -> Function {
+    Function {
+      arguments: arguments,
+      returns: returns,
+    }
   }
 }
 
@@ -36,44 +82,51 @@ impl Variable {
 #[derive(Debug, Clone)]
 pub struct SymbolTable {
   scopes: HashMap<Sid, Scope>,
+  global_sid: Sid,
+  next_sid: Sid,
 }
 
 impl SymbolTable {
   pub fn new() -> SymbolTable {
-    let sid = SymbolTable::next_sid();
-    let globals = Scope {
-      parent_sid: None,
-      variables: HashMap::new(),
+    let mut t = SymbolTable {
+      scopes: HashMap::new(),
+      global_sid: 0,
+      next_sid: 0,
     };
-    let mut t = SymbolTable { scopes: HashMap::new() };
-    t.scopes.insert(sid, globals);
+    let sid = t.allocate_sid();
+    t.global_sid = sid;
+    t.scopes.insert(sid, Scope::new(None, false));
     t
   }
 
-  pub fn next_sid() -> Sid {
-    thread_local!{
-      static CURRENT_ID: Cell<Sid> = Cell::new(0)
-    };
+  pub fn global_sid(&self) -> Sid {
+    self.global_sid
+  }
 
-    CURRENT_ID.with(|c| {
-                      let id = c.get();
-                      c.set(id + 1);
-                      id
-                    })
+  /// Scopes are numbered per table, in the order they are opened
+  fn allocate_sid(&mut self) -> Sid {
+    let sid = self.next_sid;
+    self.next_sid += 1;
+    sid
   }
 
   pub fn subscope(&mut self, parent_sid: Sid) -> Result<Sid, SymbolTableError> {
+    self.new_scope(parent_sid, false)
+  }
+
+  /// Subscope that cannot see the variables of its ancestors, only their
+  /// functions
+  pub fn isolated_subscope(&mut self, parent_sid: Sid) -> Result<Sid, SymbolTableError> {
+    self.new_scope(parent_sid, true)
+  }
+
+  fn new_scope(&mut self, parent_sid: Sid, boundary: bool) -> Result<Sid, SymbolTableError> {
     if !self.scopes.contains_key(&parent_sid) {
       return Err(SymbolTableError::Scope(parent_sid));
     }
 
-    let sid = SymbolTable::next_sid();
-    let scope = Scope {
-      parent_sid: Some(parent_sid),
-      variables: HashMap::new(),
-    };
-
-    self.scopes.insert(sid, scope);
+    let sid = self.allocate_sid();
+    self.scopes.insert(sid, Scope::new(Some(parent_sid), boundary));
     Ok(sid)
   }
 
@@ -82,13 +135,27 @@ impl SymbolTable {
                  identifier: &ast::Identifier,
                  expression: &ast::Expression)
                  -> Result<&Variable, SymbolTableError> {
-    let variable = Variable::new(expression.clone());
+    let variable = Variable::new(expression.clone(), identifier.type_name.clone());
+    self.insert(sid, identifier, variable)
+  }
 
+  pub fn declare_parameter(&mut self,
+                           sid: Sid,
+                           identifier: &ast::Identifier)
+                           -> Result<&Variable, SymbolTableError> {
+    self.insert(sid, identifier, Variable::parameter(identifier.type_name.clone()))
+  }
+
+  fn insert(&mut self,
+            sid: Sid,
+            identifier: &ast::Identifier,
+            variable: Variable)
+            -> Result<&Variable, SymbolTableError> {
     self.scopes
       .get_mut(&sid)
       .ok_or(SymbolTableError::Scope(sid))
       .and_then(|scope| {
-        let entry = scope.variables.entry(identifier.clone());
+        let entry = scope.variables.entry(identifier.symbol.clone());
 
         match entry {
           Entry::Occupied(_) => Err(SymbolTableError::Redeclare(identifier.clone())),
@@ -97,31 +164,106 @@ impl SymbolTable {
       })
   }
 
+  pub fn declare_function(&mut self,
+                          sid: Sid,
+                          identifier: &ast::Identifier,
+                          function: Function)
+                          -> Result<&Function, SymbolTableError> {
+    self.scopes
+      .get_mut(&sid)
+      .ok_or(SymbolTableError::Scope(sid))
+      .and_then(|scope| {
+        let entry = scope.functions.entry(identifier.symbol.clone());
+
+        match entry {
+          Entry::Occupied(_) => Err(SymbolTableError::Redeclare(identifier.clone())),
+          Entry::Vacant(v) => Ok(&*v.insert(function))
+        }
+      })
+  }
+
+  pub fn declare_label(&mut self,
+                       sid: Sid,
+                       identifier: &ast::Identifier)
+                       -> Result<(), SymbolTableError> {
+    self.scopes
+      .get_mut(&sid)
+      .ok_or(SymbolTableError::Scope(sid))
+      .and_then(|scope| if scope.labels.insert(identifier.symbol.clone()) {
+                  Ok(())
+                } else {
+                  Err(SymbolTableError::Redeclare(identifier.clone()))
+                })
+  }
+
   pub fn get(&self, sid: Sid, identifier: &ast::Identifier) -> Result<&Variable, SymbolTableError> {
+    self.resolve(sid, identifier)
+      .map(|declaration_sid| &self.scopes[&declaration_sid].variables[&identifier.symbol])
+  }
+
+  /// Finds the scope declaring the variable visible from `sid`
+  pub fn resolve(&self, sid: Sid, identifier: &ast::Identifier) -> Result<Sid, SymbolTableError> {
+    self.find(sid, identifier, false, |scope| scope.variables.contains_key(&identifier.symbol))
+  }
+
+  pub fn get_function(&self,
+                      sid: Sid,
+                      identifier: &ast::Identifier)
+                      -> Result<&Function, SymbolTableError> {
+    self.resolve_function(sid, identifier)
+      .map(|declaration_sid| &self.scopes[&declaration_sid].functions[&identifier.symbol])
+  }
+
+  /// Finds the scope declaring the function visible from `sid`; unlike
+  /// variables, functions remain visible inside nested function bodies
+  pub fn resolve_function(&self,
+                          sid: Sid,
+                          identifier: &ast::Identifier)
+                          -> Result<Sid, SymbolTableError> {
+    self.find(sid, identifier, true, |scope| scope.functions.contains_key(&identifier.symbol))
+  }
+
+  /// Finds the scope declaring the label visible from `sid`
+  pub fn resolve_label(&self,
+                       sid: Sid,
+                       identifier: &ast::Identifier)
+                       -> Result<Sid, SymbolTableError> {
+    self.find(sid, identifier, false, |scope| scope.labels.contains(&identifier.symbol))
+  }
+
+  fn find<F>(&self,
+             sid: Sid,
+             identifier: &ast::Identifier,
+             cross_boundaries: bool,
+             declares: F)
+             -> Result<Sid, SymbolTableError>
+    where F: Fn(&Scope) -> bool
+  {
     if !self.scopes.contains_key(&sid) {
       return Err(SymbolTableError::Scope(sid));
     }
 
-    let mut current_scope = self.scopes.get(&sid).unwrap();
+    let mut current_sid = sid;
     loop {
-      if current_scope.variables.contains_key(identifier) {
-        return Ok(current_scope.variables.get(identifier).unwrap());
+      let current_scope = &self.scopes[&current_sid];
+      if declares(current_scope) {
+        return Ok(current_sid);
       }
 
-      if let Some(parent_sid) = current_scope.parent_sid {
-        current_scope = self.scopes.get(&parent_sid).unwrap();
-      } else {
-        return Err(SymbolTableError::Unknown(identifier.clone(), sid));
+      match current_scope.parent_sid {
+        Some(parent_sid) if cross_boundaries || !current_scope.boundary => {
+          current_sid = parent_sid
+        }
+        _ => return Err(SymbolTableError::Unknown(identifier.clone(), sid)),
       }
     }
-
   }
 }
 
 #[test]
 fn it_allows_variable_declaration_in_scope() {
   let identifier = ast::Identifier::new("foo").unwrap();
-  let zero = ast::Node::new(ast::Literal::DecNumber(ast::DecNumber::new("0")));
+  let zero = ast::Literal::new(ast::LiteralValue::DecNumber(ast::DecNumber::new("0").unwrap()), None);
   let expression = ast::Expression::Literal(zero);
 
   let mut t = SymbolTable::new();
@@ -148,7 +290,7 @@ fn it_allows_variable_lookup_from_nonstrictly_ancestral_scopes() {
   let child_sid = t.subscope(declaration_sid).unwrap();
 
   let identifier = ast::Identifier::new("foo").unwrap();
-  let zero = ast::Node::new(ast::Literal::DecNumber(ast::DecNumber::new("0")));
+  let zero = ast::Literal::new(ast::LiteralValue::DecNumber(ast::DecNumber::new("0").unwrap()), None);
   let expression = ast::Expression::Literal(zero);
 
   assert!(t.get(0, &identifier).is_err());
@@ -171,9 +313,9 @@ fn it_retrieves_correct_variable_upon_overriding() {
   let child_sid = t.subscope(declaration_sid).unwrap();
 
   let identifier = ast::Identifier::new("foo").unwrap();
-  let zero = ast::Node::new(ast::Literal::DecNumber(ast::DecNumber::new("0")));
+  let zero = ast::Literal::new(ast::LiteralValue::DecNumber(ast::DecNumber::new("0").unwrap()), None);
   let first_expression = ast::Expression::Literal(zero);
-  let one = ast::Node::new(ast::Literal::DecNumber(ast::DecNumber::new("1")));
+  let one = ast::Literal::new(ast::LiteralValue::DecNumber(ast::DecNumber::new("1").unwrap()), None);
   let second_expression = ast::Expression::Literal(one);
 
   assert!(t.get(0, &identifier).is_err());
@@ -188,22 +330,82 @@ fn it_retrieves_correct_variable_upon_overriding() {
 
 
 
-struct ScopeVisitor<'v> {
+// Resolution
+//
+#[derive(Debug, Clone, PartialEq)]
+pub enum SemanticError {
+  UnknownVariable(ast::Identifier),
+  NotAVariable(ast::Identifier),
+  UnknownFunction(ast::Identifier),
+  Redeclared(ast::Identifier),
+  UsedBeforeDeclaration(ast::Identifier),
+  ArgumentCount(ast::Identifier, usize, usize),
+  ValueCount(usize, usize),
+  TypeMismatch(String, String),
+  MisplacedControlOp(ast::ControlOp),
+}
+
+impl fmt::Display for SemanticError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      SemanticError::UnknownVariable(ref i) => write!(f, "unknown variable `{}`", i.symbol),
+      SemanticError::NotAVariable(ref i) => {
+        write!(f, "cannot assign to `{}`, it is not a variable", i.symbol)
+      }
+      SemanticError::UnknownFunction(ref i) => write!(f, "unknown function `{}`", i.symbol),
+      SemanticError::Redeclared(ref i) => {
+        write!(f, "`{}` is already declared in this scope", i.symbol)
+      }
+      SemanticError::UsedBeforeDeclaration(ref i) => {
+        write!(f, "variable `{}` is used before its declaration", i.symbol)
+      }
+      SemanticError::ArgumentCount(ref i, expected, found) => {
+        write!(f, "`{}` expects {} arguments, found {}", i.symbol, expected, found)
+      }
+      SemanticError::ValueCount(expected, found) => {
+        write!(f, "expected {} values, found {}", expected, found)
+      }
+      SemanticError::TypeMismatch(ref expected, ref found) => {
+        write!(f, "expected a value of type `{}`, found `{}`", expected, found)
+      }
+      SemanticError::MisplacedControlOp(ast::ControlOp::Break) => {
+        write!(f, "`break` outside of for loop body")
+      }
+      SemanticError::MisplacedControlOp(ast::ControlOp::Continue) => {
+        write!(f, "`continue` outside of for loop body")
+      }
+      SemanticError::MisplacedControlOp(ast::ControlOp::Leave) => {
+        write!(f, "`leave` outside of function body")
+      }
+    }
+  }
+}
+
+pub struct ScopeVisitor<'v> {
   symbols: SymbolTable,
   current_sid: Sid,
   root_node: &'v ast::Node<ast::Block>,
   sids: HashMap<ast::Nid, Sid>,
   origins: HashMap<Sid, Option<ast::Nid>>,
+  resolutions: HashMap<ast::Nid, Sid>,
+  pending: HashMap<Sid, HashSet<String>>,
+  errors: Vec<(ast::Span, SemanticError)>,
 }
 
 impl<'v> ScopeVisitor<'v> {
   pub fn new(node: &'v ast::Node<ast::Block>) -> ScopeVisitor<'v> {
+    let symbols = SymbolTable::new();
+    let global_sid = symbols.global_sid();
+
     ScopeVisitor {
-      symbols: SymbolTable::new(),
-      current_sid: 0,
+      symbols: symbols,
+      current_sid: global_sid,
       root_node: node,
       sids: HashMap::new(),
       origins: HashMap::new(),
+      resolutions: HashMap::new(),
+      pending: HashMap::new(),
+      errors: vec![],
     }
   }
 
@@ -214,6 +416,246 @@ impl<'v> ScopeVisitor<'v> {
 
     &self.symbols
   }
+
+  pub fn errors(&self) -> &Vec<(ast::Span, SemanticError)> {
+    &self.errors
+  }
+
+  /// Scope declaring the identifier with the given node id, for both
+  /// declarations and uses
+  pub fn resolution(&self, nid: ast::Nid) -> Option<Sid> {
+    self.resolutions.get(&nid).cloned()
+  }
+
+  fn fail(&mut self, span: ast::Span, error: SemanticError) {
+    self.errors.push((span, error));
+  }
+
+  /// Opens a scope for the block, hoisting its functions and labels and
+  /// noting the variables it declares later on. Returns the enclosing scope
+  fn enter_block(&mut self, b: &'v ast::Node<ast::Block>) -> Sid {
+    let parent_sid = self.current_sid;
+    let sid = self.symbols.subscope(parent_sid).unwrap();
+    self.origins.insert(sid, Some(b.id));
+    self.current_sid = sid;
+
+    let mut pending = HashSet::new();
+    for statement in &b.statements {
+      match **statement {
+        ast::Statement::FunctionDefinition(ref definition) => {
+          let arguments = definition.arguments.iter().map(|a| a.type_name.clone()).collect();
+          let returns = definition.returns
+            .as_ref()
+            .map_or(vec![], |returns| returns.iter().map(|r| r.type_name.clone()).collect());
+          let function = Function::new(arguments, returns);
+
+          if self.symbols.declare_function(sid, &definition.identifier, function).is_err() {
+            let error = SemanticError::Redeclared((*definition.identifier).clone());
+            self.fail(definition.identifier.span, error);
+          }
+        }
+        ast::Statement::VariableDeclaration(ref declaration) => {
+          for identifier in &declaration.identifiers {
+            pending.insert(identifier.symbol.clone());
+          }
+        }
+        ast::Statement::LabelDefinition(ref label) => {
+          if self.symbols.declare_label(sid, &label.identifier).is_err() {
+            let error = SemanticError::Redeclared((*label.identifier).clone());
+            self.fail(label.identifier.span, error);
+          }
+        }
+        _ => {}
+      }
+    }
+    self.pending.insert(sid, pending);
+
+    parent_sid
+  }
+
+  /// Visits the statements of a block. The variables of a label's stack are
+  /// declared in the block following it, or in the rest of this one if no
+  /// block follows
+  fn visit_statements(&mut self, statements: &'v [ast::Node<ast::Statement>]) {
+    let mut items: Option<&'v Vec<ast::Node<ast::Identifier>>> = None;
+    for statement in statements {
+      match (items.take(), &**statement) {
+        (Some(items), &ast::Statement::Block(ref block)) => {
+          self.push(statement.id);
+          let parent_sid = self.enter_block(block);
+          self.declare_parameters(items);
+          self.push(block.id);
+          self.visit_statements(&block.statements);
+          self.current_sid = parent_sid;
+          continue;
+        }
+        (Some(items), _) => self.declare_parameters(items),
+        (None, _) => {}
+      }
+
+      self.visit_statement(statement);
+      if let ast::Statement::LabelDefinition(ref label) = **statement {
+        items = label.items();
+      }
+    }
+
+    if let Some(items) = items {
+      self.declare_parameters(items);
+    }
+  }
+
+  fn declare_parameters(&mut self, identifiers: &'v [ast::Node<ast::Identifier>]) {
+    for identifier in identifiers {
+      self.declare_variable(identifier, None);
+    }
+  }
+
+  fn declare_variable(&mut self,
+                      identifier: &'v ast::Node<ast::Identifier>,
+                      expression: Option<&ast::Expression>) {
+    let sid = self.current_sid;
+    self.push(identifier.id);
+    self.resolutions.insert(identifier.id, sid);
+
+    if let Some(pending) = self.pending.get_mut(&sid) {
+      pending.remove(&identifier.symbol);
+    }
+
+    let declared = match expression {
+      Some(expression) => self.symbols.declare(sid, identifier, expression).is_ok(),
+      None => self.symbols.declare_parameter(sid, identifier).is_ok(),
+    };
+
+    if !declared {
+      self.fail(identifier.span, SemanticError::Redeclared((**identifier).clone()));
+    }
+  }
+
+  /// Resolves an identifier used as a value, i.e. a variable or a jump label
+  fn resolve_variable(&mut self, identifier: &'v ast::Node<ast::Identifier>) {
+    let resolved = self.symbols
+      .resolve(self.current_sid, identifier)
+      .or_else(|_| self.symbols.resolve_label(self.current_sid, identifier));
+
+    match resolved {
+      Ok(sid) => {
+        self.resolutions.insert(identifier.id, sid);
+      }
+      Err(_) => self.fail_unresolved(identifier),
+    }
+  }
+
+  /// Resolves an identifier assigned to, which must be a variable
+  fn resolve_target(&mut self, identifier: &'v ast::Node<ast::Identifier>) {
+    self.push(identifier.id);
+
+    match self.symbols.resolve(self.current_sid, identifier) {
+      Ok(sid) => {
+        self.resolutions.insert(identifier.id, sid);
+      }
+      Err(_) if self.symbols.resolve_label(self.current_sid, identifier).is_ok() => {
+        self.fail(identifier.span, SemanticError::NotAVariable((**identifier).clone()));
+      }
+      Err(_) => self.fail_unresolved(identifier),
+    }
+  }
+
+  fn fail_unresolved(&mut self, identifier: &ast::Node<ast::Identifier>) {
+    let error = if self.is_pending(identifier) {
+      SemanticError::UsedBeforeDeclaration((**identifier).clone())
+    } else {
+      SemanticError::UnknownVariable((**identifier).clone())
+    };
+    self.fail(identifier.span, error);
+  }
+
+  /// Number of arguments and return values of a user defined function or
+  /// builtin instruction
+  fn signature(&self, identifier: &ast::Identifier) -> Option<(usize, usize)> {
+    match self.symbols.get_function(self.current_sid, identifier) {
+      Ok(function) => Some((function.arguments.len(), function.returns.len())),
+      Err(_) => Opcode::builtin(&identifier.symbol).map(|op| (op.inputs(), op.outputs())),
+    }
+  }
+
+  /// Number of values an expression leaves on the stack, if known
+  fn values(&self, expression: &ast::Expression) -> Option<usize> {
+    match *expression {
+      ast::Expression::FunctionCall(ref call) => {
+        self.signature(&call.identifier).map(|(_, returns)| returns)
+      }
+      _ => Some(1),
+    }
+  }
+
+  fn expect_values(&mut self, expression: &ast::Node<ast::Expression>, expected: usize) {
+    match self.values(expression) {
+      Some(found) if found != expected => {
+        self.fail(expression.span, SemanticError::ValueCount(expected, found))
+      }
+      _ => {}
+    }
+  }
+
+  /// Types of the values an expression leaves on the stack, if known. Values
+  /// of builtins and of unannotated variables have no type
+  fn types(&self, expression: &ast::Expression) -> Option<Vec<Option<String>>> {
+    match *expression {
+      ast::Expression::Literal(ref literal) => Some(vec![literal.type_name.clone()]),
+      ast::Expression::Identifier(ref identifier) => {
+        self.variable_type(identifier).map(|type_name| vec![type_name])
+      }
+      ast::Expression::FunctionCall(ref call) => {
+        self.symbols
+          .get_function(self.current_sid, &call.identifier)
+          .ok()
+          .map(|function| function.returns.clone())
+      }
+    }
+  }
+
+  fn variable_type(&self, identifier: &ast::Identifier) -> Option<Option<String>> {
+    self.symbols
+      .get(self.current_sid, identifier)
+      .ok()
+      .map(|variable| variable.type_name.clone())
+  }
+
+  /// Checks the types of the expression's values against those expected;
+  /// a value without a type agrees with any
+  fn expect_types(&mut self, expression: &ast::Node<ast::Expression>, expected: &[Option<String>]) {
+    let found = match self.types(expression) {
+      Some(found) => found,
+      None => return,
+    };
+
+    for (expected, found) in expected.iter().zip(found) {
+      match (expected, found) {
+        (&Some(ref expected), Some(found)) if *expected != found => {
+          self.fail(expression.span,
+                    SemanticError::TypeMismatch(expected.clone(), found))
+        }
+        _ => {}
+      }
+    }
+  }
+
+  /// Whether the variable is declared further down in one of the blocks
+  /// it is visible from
+  fn is_pending(&self, identifier: &ast::Identifier) -> bool {
+    let mut sid = self.current_sid;
+    loop {
+      if self.pending.get(&sid).map_or(false, |pending| pending.contains(&identifier.symbol)) {
+        return true;
+      }
+
+      let scope = &self.symbols.scopes[&sid];
+      match scope.parent_sid {
+        Some(parent_sid) if !scope.boundary => sid = parent_sid,
+        _ => return false,
+      }
+    }
+  }
 }
 
 impl<'v> Visitor<'v> for ScopeVisitor<'v> {
@@ -222,10 +664,147 @@ impl<'v> Visitor<'v> for ScopeVisitor<'v> {
   }
 
   fn visit_block(&mut self, b: &'v ast::Node<ast::Block>) {
-    let sid = self.symbols.subscope(self.current_sid).unwrap();
-    self.origins.insert(sid, Some(b.id));
+    let parent_sid = self.enter_block(b);
+    self.push(b.id);
+    self.visit_statements(&b.statements);
+    self.current_sid = parent_sid;
+  }
+
+  fn visit_function_definition(&mut self, f: &'v ast::Node<ast::FunctionDefinition>) {
+    // the function itself was declared when entering the enclosing block
+    let parent_sid = self.current_sid;
+    self.push(f.id);
+    self.push(f.identifier.id);
+    self.resolutions.insert(f.identifier.id, parent_sid);
+
+    let sid = self.symbols.isolated_subscope(parent_sid).unwrap();
+    self.origins.insert(sid, Some(f.id));
     self.current_sid = sid;
-    &b.walk(self);
+
+    self.declare_parameters(&f.arguments);
+    if let Some(ref returns) = f.returns {
+      self.declare_parameters(returns);
+    }
+
+    self.visit_block(&f.body);
+    self.current_sid = parent_sid;
+  }
+
+  fn visit_variable_declaration(&mut self, v: &'v ast::Node<ast::VariableDeclaration>) {
+    self.push(v.id);
+    self.visit_expression(&v.expression);
+    self.expect_values(&v.expression, v.identifiers.len());
+
+    let types: Vec<_> = v.identifiers.iter().map(|i| i.type_name.clone()).collect();
+    self.expect_types(&v.expression, &types);
+
+    for identifier in &v.identifiers {
+      self.declare_variable(identifier, Some(&v.expression));
+    }
+  }
+
+  fn visit_assignment(&mut self, a: &'v ast::Node<ast::Assignment>) {
+    self.push(a.id);
+    for identifier in &a.identifiers {
+      self.resolve_target(identifier);
+    }
+    self.visit_expression(&a.expression);
+    self.expect_values(&a.expression, a.identifiers.len());
+
+    let types: Vec<_> = a.identifiers
+      .iter()
+      .map(|i| self.variable_type(i).and_then(|type_name| type_name))
+      .collect();
+    self.expect_types(&a.expression, &types);
+  }
+
+  fn visit_if(&mut self, i: &'v ast::Node<ast::If>) {
+    i.walk(self);
+    self.expect_values(&i.condition, 1);
+  }
+
+  fn visit_switch(&mut self, s: &'v ast::Node<ast::Switch>) {
+    s.walk(self);
+    self.expect_values(&s.expression, 1);
+  }
+
+  fn visit_for_loop(&mut self, f: &'v ast::Node<ast::ForLoop>) {
+    self.push(f.id);
+
+    // variables declared in the init block are visible throughout the loop
+    let parent_sid = self.enter_block(&f.init);
+    self.push(f.init.id);
+    self.visit_statements(&f.init.statements);
+    self.visit_expression(&f.condition);
+    self.visit_block(&f.post);
+    self.visit_block(&f.body);
+    self.current_sid = parent_sid;
+  }
+
+  fn visit_sub_assembly(&mut self, a: &'v ast::Node<ast::SubAssembly>) {
+    let parent_sid = self.current_sid;
+    self.push(a.id);
+    self.push(a.identifier.id);
+
+    self.current_sid = self.symbols.isolated_subscope(parent_sid).unwrap();
+    self.visit_block(&a.block);
+    self.current_sid = parent_sid;
+  }
+
+  fn visit_stack_assignment(&mut self, a: &'v ast::Node<ast::StackAssignment>) {
+    self.push(a.id);
+    self.resolve_target(&a.identifier);
+  }
+
+  fn visit_label_definition(&mut self, l: &'v ast::Node<ast::LabelDefinition>) {
+    // labels are declared when entering their block
+    self.push(l.id);
+    self.push(l.identifier.id);
+    self.resolutions.insert(l.identifier.id, self.current_sid);
+  }
+
+  fn visit_function_call(&mut self, c: &'v ast::Node<ast::FunctionCall>) {
+    self.push(c.id);
+    self.push(c.identifier.id);
+
+    match self.symbols.resolve_function(self.current_sid, &c.identifier) {
+      Ok(sid) => {
+        self.resolutions.insert(c.identifier.id, sid);
+      }
+      Err(_) if Opcode::builtin(&c.identifier.symbol).is_some() => {}
+      Err(_) => {
+        let error = SemanticError::UnknownFunction((*c.identifier).clone());
+        self.fail(c.identifier.span, error);
+      }
+    }
+
+    match self.signature(&c.identifier) {
+      Some((arguments, _)) if arguments != c.arguments.len() => {
+        let error = SemanticError::ArgumentCount((*c.identifier).clone(),
+                                                 arguments,
+                                                 c.arguments.len());
+        self.fail(c.span, error);
+      }
+      _ => {}
+    }
+
+    let types = match self.symbols.get_function(self.current_sid, &c.identifier) {
+      Ok(function) => function.arguments.clone(),
+      Err(_) => vec![],
+    };
+
+    for (i, argument) in c.arguments.iter().enumerate() {
+      self.visit_expression(argument);
+      self.expect_values(argument, 1);
+      if let Some(expected) = types.get(i) {
+        self.expect_types(argument, &[expected.clone()]);
+      }
+    }
+  }
+
+  fn visit_identifier(&mut self, i: &'v ast::Node<ast::Identifier>) {
+    self.push(i.id);
+    self.resolve_variable(i);
   }
 }
 
@@ -267,28 +846,18 @@ fn it_creates_a_scope_for_each_block() {
 #[test]
 fn it_stores_nodes_by_scope() {
   let identifier = ast::Identifier::new("i");
-  let identifier_nid = identifier.id;
-
-  let zero = ast::Node::new(ast::Literal::DecNumber(ast::DecNumber::new("0")));
-  let zero_nid = zero.id;
-
+  let zero = ast::Literal::new(ast::LiteralValue::DecNumber(ast::DecNumber::new("0").unwrap()), None);
   let expression = ast::Node::new(ast::Expression::Literal(zero));
-  let expression_nid = expression.id;
-
   let declaration = ast::VariableDeclaration::new(vec![identifier], expression);
-  let declaration_nid = declaration.id;
-
   let statement = ast::Node::new(ast::Statement::VariableDeclaration(declaration));
-  let statement_nid = statement.id;
-
   let block = ast::Block::new(vec![statement]);
-  let block_nid = block.id;
-
   let outer_statement = ast::Node::new(ast::Statement::Block(block));
-  let outer_statement_nid = outer_statement.id;
+  let mut root = ast::Block::new(vec![outer_statement]);
 
-  let root = ast::Block::new(vec![outer_statement]);
-  let root_nid = root.id;
+  // numbered parents first, in the order of the fields
+  root.number(&mut Ids::new());
+  let (root_nid, outer_statement_nid, block_nid, statement_nid) = (0, 1, 2, 3);
+  let (declaration_nid, identifier_nid, expression_nid, zero_nid) = (4, 5, 6, 7);
 
   let mut visitor = ScopeVisitor::new(&root);
 
@@ -306,3 +875,158 @@ fn it_stores_nodes_by_scope() {
   assert_eq!(visitor.sids.get(&zero_nid), Some(&block_sid));
   assert_eq!(visitor.sids.get(&identifier_nid), Some(&block_sid));
 }
+
+#[cfg(test)]
+use asm::grammar;
+
+#[cfg(test)]
+use asm::ast::ids::{Ids, Numbered};
+
+#[cfg(test)]
+fn resolve(source: &str) -> Vec<SemanticError> {
+  let block = grammar::block(source).unwrap();
+  let mut visitor = ScopeVisitor::new(&block);
+  visitor.visit();
+  visitor.errors().iter().map(|&(_, ref error)| error.clone()).collect()
+}
+
+#[test]
+fn it_resolves_declared_names() {
+  assert_eq!(resolve("{ let x := 1 x := add(x, 1) }"), vec![]);
+  assert_eq!(resolve("{ f(1) function f(a) -> (b) { b := a } }"), vec![]);
+  assert_eq!(resolve("{ for { let i := 0 } lt(i, 10) { i := add(i, 1) } { pop(i) } }"),
+             vec![]);
+}
+
+#[test]
+fn it_resolves_uses_to_their_declaring_scope() {
+  let block = grammar::block("{ let x := 1 { pop(x) } }").unwrap();
+  let mut visitor = ScopeVisitor::new(&block);
+  visitor.visit();
+
+  let declaration_nid = match *block.statements[0] {
+    ast::Statement::VariableDeclaration(ref declaration) => declaration.identifiers[0].id,
+    _ => panic!("expected variable declaration"),
+  };
+  let use_nid = match *block.statements[1] {
+    ast::Statement::Block(ref inner) => {
+      match *inner.statements[0] {
+        ast::Statement::Expression(ref expression) => {
+          match **expression {
+            ast::Expression::FunctionCall(ref call) => {
+              match *call.arguments[0] {
+                ast::Expression::Identifier(ref identifier) => identifier.id,
+                _ => panic!("expected identifier"),
+              }
+            }
+            _ => panic!("expected function call"),
+          }
+        }
+        _ => panic!("expected expression"),
+      }
+    }
+    _ => panic!("expected block"),
+  };
+
+  assert!(visitor.resolution(declaration_nid).is_some());
+  assert_eq!(visitor.resolution(use_nid), visitor.resolution(declaration_nid));
+}
+
+#[test]
+fn it_reports_resolution_errors() {
+  let x = ast::Identifier::new("x").unwrap();
+  let f = ast::Identifier::new("f").unwrap();
+
+  assert_eq!(resolve("{ pop(x) }"), vec![SemanticError::UnknownVariable(x.clone())]);
+  assert_eq!(resolve("{ f() }"), vec![SemanticError::UnknownFunction(f.clone())]);
+  assert_eq!(resolve("{ let x := 1 let x := 2 }"),
+             vec![SemanticError::Redeclared(x.clone())]);
+  assert_eq!(resolve("{ function f() {} function f() {} }"),
+             vec![SemanticError::Redeclared(f.clone())]);
+  assert_eq!(resolve("{ pop(x) let x := 1 }"),
+             vec![SemanticError::UsedBeforeDeclaration(x.clone())]);
+  assert_eq!(resolve("{ let x := x }"),
+             vec![SemanticError::UsedBeforeDeclaration(x.clone())]);
+  assert_eq!(resolve("{ 1 =: x }"), vec![SemanticError::UnknownVariable(x.clone())]);
+}
+
+#[test]
+fn it_checks_argument_and_return_counts() {
+  let add = ast::Identifier::new("add").unwrap();
+  let f = ast::Identifier::new("f").unwrap();
+
+  assert_eq!(resolve("{ let x := add(1) }"),
+             vec![SemanticError::ArgumentCount(add, 2, 1)]);
+  assert_eq!(resolve("{ function f(a) {} f(1, 2) }"),
+             vec![SemanticError::ArgumentCount(f, 1, 2)]);
+  assert_eq!(resolve("{ let x := mstore(0, 1) }"), vec![SemanticError::ValueCount(1, 0)]);
+  assert_eq!(resolve("{ pop(mstore(0, 1)) }"), vec![SemanticError::ValueCount(1, 0)]);
+  assert_eq!(resolve("{ function f() -> (a, b) {} let (x, y, z) := f() }"),
+             vec![SemanticError::ValueCount(3, 2)]);
+  assert_eq!(resolve("{ let (x, y) := 1 }"), vec![SemanticError::ValueCount(2, 1)]);
+  assert_eq!(resolve("{ function f() -> (a, b) {} switch f() default: {} }"),
+             vec![SemanticError::ValueCount(1, 2)]);
+  assert_eq!(resolve("{ function f() -> (a, b) {} let (x, y) := f() (x, y) := f() }"),
+             vec![]);
+}
+
+#[test]
+fn it_resolves_labels() {
+  let start = ast::Identifier::new("start").unwrap();
+
+  assert_eq!(resolve("{ jump(end) start: { jump(start) } end: }"), vec![]);
+  assert_eq!(resolve("{ start: start: }"), vec![SemanticError::Redeclared(start.clone())]);
+  assert_eq!(resolve("{ start: function f() { jump(start) } }"),
+             vec![SemanticError::UnknownVariable(start.clone())]);
+  assert_eq!(resolve("{ start: start := 1 }"),
+             vec![SemanticError::NotAVariable(start.clone())]);
+  assert_eq!(resolve("{ start: 1 =: start }"),
+             vec![SemanticError::NotAVariable(start)]);
+}
+
+#[test]
+fn it_declares_label_stacks() {
+  let a = ast::Identifier::new("a").unwrap();
+
+  assert_eq!(resolve("{ jump(l) l[a]: { pop(a) } }"), vec![]);
+  assert_eq!(resolve("{ jump(l) l[a]: { } pop(a) }"),
+             vec![SemanticError::UnknownVariable(a.clone())]);
+  assert_eq!(resolve("{ jump(l) l[a, b]: pop(b) pop(a) }"), vec![]);
+  assert_eq!(resolve("{ jump(l) l[a, a]: }"), vec![SemanticError::Redeclared(a)]);
+}
+
+#[test]
+fn it_scopes_if_bodies() {
+  let y = ast::Identifier::new("y").unwrap();
+
+  assert_eq!(resolve("{ let x := 1 if x { let y := x } }"), vec![]);
+  assert_eq!(resolve("{ if 1 { let y := 1 } pop(y) }"),
+             vec![SemanticError::UnknownVariable(y)]);
+  assert_eq!(resolve("{ function f() -> (a, b) { } if f() { } }"),
+             vec![SemanticError::ValueCount(1, 2)]);
+}
+
+#[test]
+fn it_hides_outer_variables_from_function_bodies() {
+  let x = ast::Identifier::new("x").unwrap();
+
+  assert_eq!(resolve("{ let x := 1 function f() { pop(x) } }"),
+             vec![SemanticError::UnknownVariable(x)]);
+  assert_eq!(resolve("{ function f() { g() } function g() {} }"), vec![]);
+}
+
+#[test]
+fn it_checks_type_agreement() {
+  let mismatch = |expected: &str, found: &str| {
+    SemanticError::TypeMismatch(expected.to_string(), found.to_string())
+  };
+
+  assert_eq!(resolve("{ let x:u256 := 1:u256 let y := x x := y }"), vec![]);
+  assert_eq!(resolve("{ let x:u256 := 1:u32 }"), vec![mismatch("u256", "u32")]);
+  assert_eq!(resolve("{ let x:bool := 1 let y:u8 := x x := 2:u8 }"),
+             vec![mismatch("u8", "bool"), mismatch("bool", "u8")]);
+  assert_eq!(resolve("{ function f(a:u32) -> r:bool { } let x:u32 := f(2:u32) f(x) }"),
+             vec![mismatch("u32", "bool")]);
+  assert_eq!(resolve("{ function f(a:u32, b) { } f(1:u8, 2:u8) }"),
+             vec![mismatch("u32", "u8")]);
+}
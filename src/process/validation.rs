@@ -0,0 +1,127 @@
+//! Structural checks that do not depend on name resolution
+use asm::ast;
+use asm::ast::visitor::Visitor;
+use asm::ast::visitor::Walkable;
+use process::symbols::SemanticError;
+
+/// Checks that `break` and `continue` only appear within for loop bodies,
+/// and `leave` within function bodies. Function bodies, sub-assemblies and
+/// the init and post blocks of a loop are not part of any enclosing loop
+/// body, and sub-assemblies are not part of any enclosing function
+pub struct ControlOpVisitor<'v> {
+  root_node: &'v ast::Node<ast::Block>,
+  in_loop_body: bool,
+  in_function_body: bool,
+  errors: Vec<(ast::Span, SemanticError)>,
+}
+
+impl<'v> ControlOpVisitor<'v> {
+  pub fn new(node: &'v ast::Node<ast::Block>) -> ControlOpVisitor<'v> {
+    ControlOpVisitor {
+      root_node: node,
+      in_loop_body: false,
+      in_function_body: false,
+      errors: vec![],
+    }
+  }
+
+  pub fn visit(&mut self) -> &Vec<(ast::Span, SemanticError)> {
+    {
+      self.visit_block(self.root_node);
+    }
+
+    &self.errors
+  }
+}
+
+impl<'v> Visitor<'v> for ControlOpVisitor<'v> {
+  fn visit_function_definition(&mut self, f: &'v ast::Node<ast::FunctionDefinition>) {
+    let (in_loop_body, in_function_body) = (self.in_loop_body, self.in_function_body);
+    self.in_loop_body = false;
+    self.in_function_body = true;
+    f.walk(self);
+    self.in_loop_body = in_loop_body;
+    self.in_function_body = in_function_body;
+  }
+
+  fn visit_for_loop(&mut self, f: &'v ast::Node<ast::ForLoop>) {
+    let in_loop_body = self.in_loop_body;
+
+    self.in_loop_body = false;
+    self.visit_block(&f.init);
+    self.visit_expression(&f.condition);
+    self.visit_block(&f.post);
+
+    self.in_loop_body = true;
+    self.visit_block(&f.body);
+
+    self.in_loop_body = in_loop_body;
+  }
+
+  fn visit_sub_assembly(&mut self, a: &'v ast::Node<ast::SubAssembly>) {
+    let (in_loop_body, in_function_body) = (self.in_loop_body, self.in_function_body);
+    self.in_loop_body = false;
+    self.in_function_body = false;
+    a.walk(self);
+    self.in_loop_body = in_loop_body;
+    self.in_function_body = in_function_body;
+  }
+
+  fn visit_control_op(&mut self, o: &'v ast::Node<ast::ControlOp>) {
+    let allowed = match **o {
+      ast::ControlOp::Break | ast::ControlOp::Continue => self.in_loop_body,
+      ast::ControlOp::Leave => self.in_function_body,
+    };
+
+    if !allowed {
+      self.errors.push((o.span, SemanticError::MisplacedControlOp((**o).clone())));
+    }
+  }
+}
+
+#[cfg(test)]
+use asm::grammar;
+
+#[cfg(test)]
+fn validate(source: &str) -> Vec<SemanticError> {
+  let block = grammar::block(source).unwrap();
+  let mut visitor = ControlOpVisitor::new(&block);
+  visitor.visit().iter().map(|&(_, ref error)| error.clone()).collect()
+}
+
+#[test]
+fn it_accepts_control_ops_in_loop_bodies() {
+  assert_eq!(validate("{ for {} 1 {} { break } }"), vec![]);
+  assert_eq!(validate("{ for {} 1 {} { { switch 1 case 1: { continue } } } }"), vec![]);
+  assert_eq!(validate("{ for {} 1 {} { for {} 1 {} {} break } }"), vec![]);
+}
+
+#[test]
+fn it_rejects_misplaced_control_ops() {
+  let break_op = SemanticError::MisplacedControlOp(ast::ControlOp::Break);
+  let continue_op = SemanticError::MisplacedControlOp(ast::ControlOp::Continue);
+
+  assert_eq!(validate("{ break }"), vec![break_op.clone()]);
+  assert_eq!(validate("{ for {} 1 { continue } {} }"), vec![continue_op.clone()]);
+  assert_eq!(validate("{ for {} 1 {} { function f() { break } } }"), vec![break_op.clone()]);
+}
+
+#[test]
+fn it_accepts_leave_only_in_function_bodies() {
+  let leave_op = SemanticError::MisplacedControlOp(ast::ControlOp::Leave);
+
+  assert_eq!(validate("{ function f() { for {} 1 {} { if 1 { leave } } } }"), vec![]);
+  assert_eq!(validate("{ leave }"), vec![leave_op.clone()]);
+  assert_eq!(validate("{ for {} 1 {} { leave } }"), vec![leave_op.clone()]);
+  assert_eq!(validate("{ function f() { assembly a { leave } } }"), vec![leave_op.clone()]);
+}
+
+#[test]
+fn it_locates_misplaced_control_ops() {
+  let block = grammar::block("{\n  continue\n}").unwrap();
+  let mut visitor = ControlOpVisitor::new(&block);
+  let errors = visitor.visit();
+
+  assert_eq!(errors.len(), 1);
+  assert_eq!(errors[0].0, ast::Span::new(4, 12));
+}
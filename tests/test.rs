@@ -3,6 +3,7 @@ mod tests {
   extern crate solasm;
   extern crate bigint;
   use self::solasm::asm::grammar;
+  use self::solasm::asm::ast::structural::Structural;
   // use self::solasm::grammar::*;
   // use self::solasm::ast::{Statement, Expression, ControlOp};
   // use self::bigint::U256;
@@ -102,6 +103,154 @@ mod tests {
     assert_parses_ok(assembly);
   }
 
+  #[test]
+  fn it_parses_ifs() {
+    let assembly = r#"{
+            function max(a, b) -> (c)
+            {
+                c := a
+                if lt(a, b) { c := b }
+            }
+        }"#;
+    assert_parses_ok(assembly);
+    assert!(grammar::block("{ if := 1 }").is_err());
+    assert!(grammar::if_statement("if 1").is_err());
+  }
+
+  #[test]
+  fn it_parses_types() {
+    let assembly = r#"{
+            function f(a:u32, b:u32) -> r:bool { r := lt(a, b) }
+            let x:u256 := 1:u256
+            let (y:bool, z) := f(0x20:u32, 2)
+            switch x case 1:u256: { }
+        }"#;
+    assert_parses_ok(assembly);
+
+    let declaration = grammar::variable_declaration("let x:u256 := 1:u256").unwrap();
+    assert_eq!(declaration.identifiers[0].type_name, Some("u256".to_string()));
+    assert_eq!(grammar::literal("\"abc\":bytes32").unwrap().type_name,
+               Some("bytes32".to_string()));
+
+    // the colon binds tightly, so as not to be read as a label or case
+    assert!(grammar::block("{ let x : u256 := 1 }").is_err());
+  }
+
+  #[test]
+  fn it_parses_objects() {
+    let assembly = r#"
+        object "Token" {
+            code {
+                datacopy(0, dataoffset("Runtime"), datasize("Runtime"))
+                return(0, datasize("Runtime"))
+            }
+            object "Runtime" {
+                code { mstore(0, 1) }
+                data "Table" hex"4123"
+            }
+            data "Name" "token"
+        }"#;
+    let object = grammar::object(assembly).unwrap();
+    assert_eq!(object.name, "Token");
+    assert_eq!(object.code.statements.len(), 2);
+    assert_eq!(object.items.len(), 2);
+
+    // `code` and `data` are only keywords within objects
+    assert!(grammar::block("{ let code := data }").is_ok());
+    assert!(grammar::object("object \"Token\" { }").is_err());
+    assert!(grammar::object("object \"Token\" { code { } code { } }").is_err());
+  }
+
+  #[test]
+  fn it_parses_equivalent_sources_alike() {
+    let equivalents = [
+      ("{ let x := add(1, y) }", "{\n  // sum\n  let x := add(1, /* one */ y)\n}"),
+      ("{ let x := 1 }", "{ let (x) := 1 }"),
+      ("{ function f(a) -> r { } }", "{ function f(a) -> (r) { } }"),
+      ("{ let s := \"a'b\" }", r#"{ let s := 'a\'b' }"#),
+      ("{ let s := \"ab\" }", r#"{ let s := "\x61\u0062" }"#),
+    ];
+    for &(a, b) in &equivalents {
+      assert_eq!(grammar::block(a).unwrap().structure(),
+                 grammar::block(b).unwrap().structure());
+    }
+
+    let differences = [
+      ("{ let x := 1 }", "{ let x := 0x1 }"),
+      ("{ let x := 1 }", "{ let x := 1:u256 }"),
+      ("{ f(1, 2) }", "{ f(2, 1) }"),
+      ("{ switch x case 1: { } }", "{ switch x case 1: { } default: { } }"),
+    ];
+    for &(a, b) in &differences {
+      assert!(grammar::block(a).unwrap().structure() != grammar::block(b).unwrap().structure());
+    }
+  }
+
+  #[test]
+  fn it_records_source_spans() {
+    let assembly = "{\n  let x := add(1, y)\n}\n";
+    let block = grammar::block(assembly).unwrap();
+    assert_eq!((block.span.start, block.span.end), (0, 24));
+
+    let statement = &block.statements[0];
+    assert_eq!(&assembly[statement.span.start..statement.span.end], "let x := add(1, y)");
+
+    let position = statement.span.position(assembly);
+    assert_eq!((position.line, position.column), (2, 3));
+
+    let end = statement.span.end_position(assembly);
+    assert_eq!((end.line, end.column), (2, 21));
+  }
+
+  #[test]
+  fn it_parses_labels() {
+    assert_eq!(grammar::label_definition("x:").unwrap().identifier.symbol,
+               "x".to_string());
+    assert!(grammar::label_definition("x :=").is_err());
+
+    let assembly = r#"{
+            let i := 0
+            loop:
+            i := add(i, 1)
+            jumpi(loop, lt(i, 10))
+        }"#;
+    assert_parses_ok(assembly);
+  }
+
+  #[test]
+  fn it_parses_instructions() {
+    assert!(grammar::instruction("dup1").is_ok());
+    assert!(grammar::instruction("frobinate").is_err());
+    assert!(grammar::instruction("push1").is_err());
+    assert!(grammar::instruction("mstore(0, 1)").is_err());
+    assert_eq!(grammar::stack_assignment("=: x").unwrap().identifier.symbol,
+               "x".to_string());
+
+    let assembly = r#"{
+            let x := 0
+            0x40
+            mload
+            dup1
+            =: x
+            pop
+        }"#;
+    assert_parses_ok(assembly);
+  }
+
+  #[test]
+  fn it_parses_string_escapes() {
+    assert_eq!(grammar::string_literal(r#""a\"b\\c\n\t""#).unwrap().bytes,
+               b"a\"b\\c\n\t".to_vec());
+    assert_eq!(grammar::string_literal(r#"'it\'s "quoted"'"#).unwrap().bytes,
+               b"it's \"quoted\"".to_vec());
+    assert_eq!(grammar::string_literal(r#""\x00\xff\u00e9""#).unwrap().bytes,
+               vec![0x00, 0xff, 0xc3, 0xa9]);
+    assert_eq!(grammar::string_literal("\"a\\\nb\"").unwrap().bytes,
+               b"ab".to_vec());
+    assert!(grammar::string_literal(r#""\q""#).is_err());
+    assert!(grammar::string_literal(r#""\ud800""#).is_err());
+  }
+
   fn assert_parses_ok(assembly: &str) {
     let result = grammar::block(assembly);
     match result {